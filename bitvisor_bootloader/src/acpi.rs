@@ -0,0 +1,157 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! ACPI Table Modification
+//!
+//! Replace an ACPI table with the one made by BitVisor without bsdriver.efi.
+//! EFI_ACPI_TABLE_PROTOCOL is used if the firmware has it, otherwise the XSDT and RSDT entries
+//! are rewritten.
+//!
+
+use crate::uefi::acpi_table::{get_table_length, replace_acpi_table};
+use crate::uefi::acpi_table_protocol::{EfiAcpiSdtProtocol, EfiAcpiTableProtocol};
+use crate::uefi::boot_service::EfiBootServices;
+use crate::uefi::{EfiConfigurationTable, EfiStatus, EfiSystemTable, EFI_ACPI_20_TABLE_GUID};
+
+/// Replace the table which has `signature` with the table at `table_address`
+///
+/// If no table has `signature`, the new table is added when EFI_ACPI_TABLE_PROTOCOL is available.
+///
+/// # Arguments
+/// * `b_s` - EfiBootService
+/// * `system_table` - EfiSystemTable to find RSDP
+/// * `signature` - the signature of the table in little endian, like `u32::from_le_bytes(*b"DMAR")`
+/// * `table_address` - the address of the new table
+///
+/// # Result
+/// If the table is replaced, Ok(()), otherwise Err(EfiStatus)
+pub fn modify_acpi_table(
+    b_s: &EfiBootServices,
+    system_table: &EfiSystemTable,
+    signature: u32,
+    table_address: usize,
+) -> Result<(), EfiStatus> {
+    let signature = signature.to_le_bytes();
+    if let (Ok(acpi_table_protocol), Ok(acpi_sdt_protocol)) = (
+        EfiAcpiTableProtocol::locate(b_s),
+        EfiAcpiSdtProtocol::locate(b_s),
+    ) {
+        return install_acpi_table(
+            b_s,
+            acpi_table_protocol,
+            acpi_sdt_protocol,
+            &signature,
+            table_address,
+        );
+    }
+
+    debug!("EFI_ACPI_TABLE_PROTOCOL is not found, rewrite XSDT and RSDT");
+    let rsdp_address = find_rsdp(system_table).ok_or(EfiStatus::EfiNotFound)?;
+    match replace_acpi_table(rsdp_address, &signature, table_address) {
+        Ok(old_table_address) => {
+            debug!(
+                "Replace {} at {:#X} with {:#X}",
+                SignatureStr(&signature),
+                old_table_address,
+                table_address
+            );
+            Ok(())
+        }
+        Err(e) => {
+            warn!(
+                "Failed to replace {} in XSDT and RSDT: {:?}",
+                SignatureStr(&signature),
+                e
+            );
+            Err(EfiStatus::EfiNotFound)
+        }
+    }
+}
+
+/// Uninstall the old table and install the copy of the new table
+///
+/// The firmware frees the old table when it is uninstalled, so a copy of it is kept
+/// and installed again if the new table is rejected.
+fn install_acpi_table(
+    b_s: &EfiBootServices,
+    acpi_table_protocol: &EfiAcpiTableProtocol,
+    acpi_sdt_protocol: &EfiAcpiSdtProtocol,
+    signature: &[u8; 4],
+    table_address: usize,
+) -> Result<(), EfiStatus> {
+    let table = unsafe {
+        core::slice::from_raw_parts(table_address as *const u8, get_table_length(table_address))
+    };
+    match acpi_sdt_protocol.find_acpi_table(signature) {
+        Ok((table_key, old_table_address)) => {
+            let old_table_length = get_table_length(old_table_address);
+            let backup_address = b_s.alloc_pool(old_table_length)?;
+            let backup = unsafe {
+                core::slice::from_raw_parts_mut(backup_address as *mut u8, old_table_length)
+            };
+            backup.copy_from_slice(unsafe {
+                core::slice::from_raw_parts(old_table_address as *const u8, old_table_length)
+            });
+            let mut result = acpi_table_protocol.uninstall_acpi_table(table_key);
+            if result.is_ok() {
+                debug!("Uninstall {}", SignatureStr(signature));
+                if let Err(e) = acpi_table_protocol.install_acpi_table(table) {
+                    warn!(
+                        "Failed to install {}: {:?}, restore the old table",
+                        SignatureStr(signature),
+                        e
+                    );
+                    if let Err(e) = acpi_table_protocol.install_acpi_table(backup) {
+                        warn!("Failed to restore {}: {:?}", SignatureStr(signature), e);
+                    }
+                    result = Err(e);
+                }
+            }
+            let _ = b_s.free_pool(backup_address);
+            result?;
+        }
+        Err(_) => {
+            debug!("{} is not installed", SignatureStr(signature));
+            acpi_table_protocol.install_acpi_table(table)?;
+        }
+    }
+    debug!(
+        "Install {} from {:#X}",
+        SignatureStr(signature),
+        table_address
+    );
+    Ok(())
+}
+
+/// Find RSDP of ACPI 2.0 or later in the configuration table
+fn find_rsdp(system_table: &EfiSystemTable) -> Option<usize> {
+    for i in 0..system_table.num_table_entries {
+        let table = unsafe {
+            &*((system_table.configuration_table
+                + i * core::mem::size_of::<EfiConfigurationTable>())
+                as *const EfiConfigurationTable)
+        };
+        if table.vendor_guid == EFI_ACPI_20_TABLE_GUID {
+            return Some(table.vendor_table);
+        }
+    }
+    None
+}
+
+/// Display the table signature as ASCII
+struct SignatureStr<'a>(&'a [u8; 4]);
+
+impl core::fmt::Display for SignatureStr<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for &c in self.0 {
+            let c = if c.is_ascii_graphic() { c as char } else { '?' };
+            core::fmt::Write::write_char(f, c)?;
+        }
+        Ok(())
+    }
+}
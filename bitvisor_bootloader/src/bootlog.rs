@@ -0,0 +1,108 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Boot Log
+//!
+//! Keep the console output in a ring buffer, and save it to a file on the ESP.
+//! When the buffer is full, the oldest output is overwritten.
+//!
+
+use crate::uefi::boot_service::EfiBootServices;
+use crate::uefi::file::EfiFileProtocol;
+use crate::uefi::EfiStatus;
+
+pub const BOOT_LOG_SIZE: usize = 64 * 1024;
+
+pub struct BootLog {
+    buffer: [u8; BOOT_LOG_SIZE],
+    /// The index of the oldest byte
+    start: usize,
+    length: usize,
+}
+
+static mut BOOT_LOG: BootLog = BootLog::new();
+
+impl BootLog {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; BOOT_LOG_SIZE],
+            start: 0,
+            length: 0,
+        }
+    }
+
+    pub fn append(&mut self, data: &[u8]) {
+        /* Only the last BOOT_LOG_SIZE bytes can be kept */
+        let data = &data[data.len().saturating_sub(BOOT_LOG_SIZE)..];
+        let end = (self.start + self.length) % BOOT_LOG_SIZE;
+        let first_length = data.len().min(BOOT_LOG_SIZE - end);
+        self.buffer[end..(end + first_length)].copy_from_slice(&data[..first_length]);
+        self.buffer[..(data.len() - first_length)].copy_from_slice(&data[first_length..]);
+
+        let length = self.length + data.len();
+        if length > BOOT_LOG_SIZE {
+            self.start = (self.start + length - BOOT_LOG_SIZE) % BOOT_LOG_SIZE;
+        }
+        self.length = length.min(BOOT_LOG_SIZE);
+    }
+
+    /// Get the log from the oldest as two slices
+    pub fn as_slices(&self) -> (&[u8], &[u8]) {
+        let first_length = self.length.min(BOOT_LOG_SIZE - self.start);
+        (
+            &self.buffer[self.start..(self.start + first_length)],
+            &self.buffer[..(self.length - first_length)],
+        )
+    }
+}
+
+/// Add the output to the boot log
+pub fn append(data: &[u8]) {
+    unsafe { (*core::ptr::addr_of_mut!(BOOT_LOG)).append(data) };
+}
+
+/// Copy the boot log into a new pool buffer
+///
+/// The ring buffer keeps changing while the loader prints, so pass the copy to BitVisor.
+///
+/// # Result
+/// If the buffer is allocated, Ok((the address of the copy, the size)), otherwise Err(EfiStatus)
+pub fn copy_to_pool(b_s: &EfiBootServices) -> Result<(usize, usize), EfiStatus> {
+    let boot_log = unsafe { &*core::ptr::addr_of!(BOOT_LOG) };
+    let (first, second) = boot_log.as_slices();
+    let size = first.len() + second.len();
+    let address = b_s.alloc_pool(size.max(1))?;
+    let copy = unsafe { core::slice::from_raw_parts_mut(address as *mut u8, size) };
+    copy[..first.len()].copy_from_slice(first);
+    copy[first.len()..].copy_from_slice(second);
+    Ok((address, size))
+}
+
+/// Write the boot log to the file, the old contents are removed
+///
+/// # Arguments
+/// * `root_protocol` - the root directory of the boot volume
+/// * `path` - the null-terminated path of the file in UTF-16
+///
+/// # Result
+/// If the whole log is written, Ok(()), otherwise Err(EfiStatus)
+pub fn save(root_protocol: &EfiFileProtocol, path: &[u16]) -> Result<(), EfiStatus> {
+    /* Delete the file to truncate it, EFI_FILE_PROTOCOL.Write never shrinks the file */
+    if let Ok(old_file) = EfiFileProtocol::create_file(root_protocol, path) {
+        let _ = old_file.delete();
+    }
+    let file = EfiFileProtocol::create_file(root_protocol, path)?;
+    let boot_log = unsafe { &*core::ptr::addr_of!(BOOT_LOG) };
+    let (first, second) = boot_log.as_slices();
+    let result = file
+        .write_all(first)
+        .and_then(|_| file.write_all(second))
+        .and_then(|_| file.flush());
+    let _ = file.close_file();
+    result
+}
@@ -1,28 +1,57 @@
+//!
+//! Boot Service Driver
+//!
+//! bsdriver.efi is embedded into the loader by the `bsdriver-embed` feature,
+//! or read from the ESP before BitVisor starts by the `bsdriver-file` feature.
+//!
+
+#[cfg(feature = "bsdriver-file")]
+use crate::uefi::file::{EfiFileProtocol, FileBuffer};
 use crate::uefi::{
-    boot_service::{DevicePathProtocol, EfiBootServices},
+    boot_service::{EfiBootServices, EFI_OPEN_PROTOCOL_GET_PROTOCOL},
     loaded_image::{EfiLoadedImageProtocol, EFI_LOADED_IMAGE_PROTOCOL_GUID},
     EfiHandle, EfiStatus, EfiSystemTable,
 };
-use core::{
-    include_bytes,
-    ptr::null_mut,
-};
+use core::ptr::{addr_of, addr_of_mut};
+
+/// The default file name of the driver in the boot directory
+#[cfg(feature = "bsdriver-file")]
+pub const BSDRIVER_NAME: &str = "bsdriver.efi";
 
 static mut ALREADY_LOADED: bool = false;
 static mut DRIVER_DATA: Option<&BootServiceDriver> = None;
-static BSDRIVER_BIN: &[u8] = include_bytes!("../../build/bsdriver.efi"); //TODO
+/// The entries are filled by bsdriver.efi, it receives the address by LoadOptions
+static mut BOOT_SERVICE_DRIVER: BootServiceDriver = BootServiceDriver {
+    acpi_table_mod: None,
+};
+#[cfg(feature = "bsdriver-embed")]
+static BSDRIVER_BIN: &[u8] = core::include_bytes!("../../build/bsdriver.efi");
+#[cfg(feature = "bsdriver-file")]
+static mut BSDRIVER_FILE: Option<FileBuffer> = None;
 
 /// load boot service driver
 /// https://github.com/matsu/bitvisor/commit/d62ffe23fe23a4beed69314784b35927bccab847
 #[repr(C)]
 pub struct BootServiceDriver {
-    pub acpi_table_mod: extern "C" fn(
-        system_table: *mut EfiSystemTable,
-        signature: u32,
-        table_addr: u64,
-    ) -> EfiStatus,
+    pub acpi_table_mod: Option<
+        extern "C" fn(
+            system_table: *mut EfiSystemTable,
+            signature: u32,
+            table_addr: u64,
+        ) -> EfiStatus,
+    >,
 }
 
+/// Load bsdriver.efi from the memory and start it
+///
+/// The driver is loaded only once, the later calls return the first result.
+///
+/// # Arguments
+/// * `image_handle` - the handle of this loader, it becomes the parent of the driver
+/// * `boot_service` - EfiBootService
+///
+/// # Result
+/// If the driver is started and fills BootServiceDriver, Some(&BootServiceDriver), otherwise None
 pub fn load_bsdriver(
     image_handle: EfiHandle,
     boot_service: &EfiBootServices,
@@ -30,57 +59,103 @@ pub fn load_bsdriver(
     if unsafe { ALREADY_LOADED } {
         return unsafe { DRIVER_DATA };
     }
-    let boot_service_driver: *mut BootServiceDriver = null_mut();
-    let mut handle = 0;
-    let interface = null_mut();
+    unsafe { ALREADY_LOADED = true };
 
-    let device_path = DevicePathProtocol {
-        major_type: 0,
-        sub_type: unsafe { *BSDRIVER_BIN.as_ptr() },
-        length: (BSDRIVER_BIN.len() as u16).to_le_bytes(),
-    };
-    if EfiStatus::EfiSuccess
-        != (boot_service.load_image)(false as u8, image_handle, &device_path, &mut handle)
-    {
-        unsafe { ALREADY_LOADED = true };
+    let Some(bsdriver_bin) = get_bsdriver_bin() else {
+        warn!("bsdriver.efi is not read");
         return None;
-    }
-    if EfiStatus::EfiSuccess
-        != (boot_service.open_protocol)(
-            handle,
-            &EFI_LOADED_IMAGE_PROTOCOL_GUID,
-            interface,
-            image_handle,
-            0,
-            0x00000002, //EFI_OPEN_PROTOCOL_GET_PROTOCOL
-        )
-    {
-        unsafe { ALREADY_LOADED = true };
-        return None;
-    }
+    };
+    let result = boot_service.load_image(image_handle, bsdriver_bin);
+    /* LoadImage copies the image, the file is no longer needed */
+    free_bsdriver_file(boot_service);
+    let handle = match result {
+        Ok(handle) => handle,
+        Err(e) => {
+            warn!("Failed to load bsdriver.efi: {:?}", e);
+            return None;
+        }
+    };
+
+    let loaded_image = match boot_service.open_protocol(
+        handle,
+        &EFI_LOADED_IMAGE_PROTOCOL_GUID,
+        image_handle,
+        0,
+        EFI_OPEN_PROTOCOL_GET_PROTOCOL,
+    ) {
+        Ok(interface) => interface as *mut EfiLoadedImageProtocol,
+        Err(e) => {
+            warn!(
+                "Failed to open LoadedImageProtocol of bsdriver.efi: {:?}",
+                e
+            );
+            let _ = boot_service.unload_image(handle);
+            return None;
+        }
+    };
 
-    let loaded_image: &mut EfiLoadedImageProtocol =
-        unsafe { &mut *(interface as *mut EfiLoadedImageProtocol) };
-    loaded_image.load_option_size = size_of::<BootServiceDriver>() as u32; // usize to u32
-    loaded_image.load_options = boot_service_driver as usize;
+    /* Pass the writable BootServiceDriver to the driver */
+    unsafe {
+        (*loaded_image).load_option_size = core::mem::size_of::<BootServiceDriver>() as u32;
+        (*loaded_image).load_options = addr_of_mut!(BOOT_SERVICE_DRIVER) as usize;
+    }
+    let _ = boot_service.close_protocol(handle, &EFI_LOADED_IMAGE_PROTOCOL_GUID, image_handle, 0);
 
-    if EfiStatus::EfiSuccess
-        != (boot_service.close_protocol)(handle, &EFI_LOADED_IMAGE_PROTOCOL_GUID, image_handle, 0)
-    {
-        unsafe { ALREADY_LOADED = true };
+    if let Err(e) = boot_service.start_image(handle) {
+        warn!("Failed to start bsdriver.efi: {:?}", e);
         return None;
     }
 
-    if EfiStatus::EfiSuccess != (boot_service.start_image)(handle, null_mut() as *mut usize, 0) {
-        unsafe { ALREADY_LOADED = true };
+    let boot_service_driver = unsafe { &*addr_of!(BOOT_SERVICE_DRIVER) };
+    if boot_service_driver.acpi_table_mod.is_none() {
+        warn!("bsdriver.efi did not fill BootServiceDriver");
         return None;
     }
+    unsafe { DRIVER_DATA = Some(boot_service_driver) };
+    unsafe { DRIVER_DATA }
+}
 
-    if boot_service_driver == null_mut() {
-        unsafe { ALREADY_LOADED = true };
-        return None;
+#[cfg(feature = "bsdriver-embed")]
+fn get_bsdriver_bin() -> Option<&'static [u8]> {
+    Some(BSDRIVER_BIN)
+}
+
+#[cfg(feature = "bsdriver-embed")]
+fn free_bsdriver_file(_boot_service: &EfiBootServices) {}
+
+#[cfg(feature = "bsdriver-file")]
+fn get_bsdriver_bin() -> Option<&'static [u8]> {
+    unsafe { (*addr_of!(BSDRIVER_FILE)).as_ref() }.map(|f| f.as_slice())
+}
+
+#[cfg(feature = "bsdriver-file")]
+fn free_bsdriver_file(boot_service: &EfiBootServices) {
+    if let Some(file) = unsafe { (*addr_of_mut!(BSDRIVER_FILE)).take() } {
+        file.free(boot_service);
     }
-    unsafe { ALREADY_LOADED = true };
-    unsafe { DRIVER_DATA = Some(&*boot_service_driver) }
-    return unsafe { DRIVER_DATA };
+}
+
+/// Read bsdriver.efi from the ESP to load it later
+///
+/// The file must be read before BitVisor starts, because the root directory is closed after that.
+///
+/// # Arguments
+/// * `root_protocol` - the root directory of the boot volume
+/// * `b_s` - EfiBootService
+/// * `path` - the null-terminated path of the driver in UTF-16
+///
+/// # Result
+/// If the whole file is read, Ok(()), otherwise Err(EfiStatus)
+#[cfg(feature = "bsdriver-file")]
+pub fn read_bsdriver(
+    root_protocol: &EfiFileProtocol,
+    b_s: &EfiBootServices,
+    path: &[u16],
+) -> Result<(), EfiStatus> {
+    let file = EfiFileProtocol::open_file(root_protocol, path)?;
+    let result = file.read_to_end(b_s);
+    let _ = file.close_file();
+    free_bsdriver_file(b_s);
+    unsafe { BSDRIVER_FILE = Some(result?) };
+    Ok(())
 }
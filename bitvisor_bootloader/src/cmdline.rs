@@ -0,0 +1,247 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Command Line
+//!
+//! Parse the LoadOptions of the loaded image as a command line like `path=\EFI\bv.elf debug`.
+//! Arguments are separated by spaces, and double quotes keep spaces in an argument.
+//!
+
+pub const MAX_COMMAND_LINE_LENGTH: usize = 1024;
+
+pub struct CommandLine {
+    /// UTF-8 command line terminated by a null character
+    buffer: [u8; MAX_COMMAND_LINE_LENGTH],
+    length: usize,
+}
+
+impl CommandLine {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; MAX_COMMAND_LINE_LENGTH],
+            length: 0,
+        }
+    }
+
+    /// Build the command line from the UTF-16 LoadOptions
+    ///
+    /// The UEFI Shell passes the image path as the first argument, so it is removed if it
+    /// ends with ".efi". LoadOptions which are not a text, like the binary OptionalData of
+    /// a Boot#### entry, are ignored. The last byte is dropped if the length is odd.
+    ///
+    /// # Arguments
+    /// * `load_options` - the LoadOptions in UTF-16 little endian
+    ///
+    /// # Result
+    /// CommandLine, it may be truncated to MAX_COMMAND_LINE_LENGTH - 1 bytes
+    pub fn from_load_options(load_options: &[u8]) -> Self {
+        let mut command_line = Self::new();
+        let characters = load_options
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&c| c != 0);
+        for c in char::decode_utf16(characters) {
+            let Ok(c) = c else {
+                return Self::new();
+            };
+            if c.is_control() && c != '\t' {
+                return Self::new();
+            }
+            let c = if c == '\t' { ' ' } else { c };
+            if command_line.length + c.len_utf8() >= MAX_COMMAND_LINE_LENGTH {
+                break;
+            }
+            c.encode_utf8(&mut command_line.buffer[command_line.length..]);
+            command_line.length += c.len_utf8();
+        }
+
+        let text = command_line.as_str();
+        let rest = match split_first_arg(text) {
+            Some((program, rest))
+                if program.len() >= 4
+                    && program.as_bytes()[program.len() - 4..].eq_ignore_ascii_case(b".efi") =>
+            {
+                rest
+            }
+            _ => text,
+        }
+        .trim_matches(' ');
+        let start = rest.as_ptr() as usize - text.as_ptr() as usize;
+        let end = start + rest.len();
+        command_line.buffer.copy_within(start..end, 0);
+        command_line.length = end - start;
+        command_line.buffer[command_line.length..].fill(0);
+        command_line
+    }
+
+    /// Get the whole command line without the program name
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buffer[..self.length]).unwrap_or("")
+    }
+
+    /// Get the command line terminated by a null character to pass to BitVisor
+    pub fn as_bytes_with_nul(&self) -> &[u8] {
+        &self.buffer[..self.length + 1]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Iterate the arguments with double quotes removed
+    pub fn args(&self) -> impl Iterator<Item = &str> {
+        let mut rest = self.as_str();
+        core::iter::from_fn(move || {
+            let (arg, next) = split_first_arg(rest)?;
+            rest = next;
+            Some(arg)
+        })
+    }
+
+    /// Check if the switch like `debug` exists
+    pub fn has_switch(&self, name: &str) -> bool {
+        self.args().any(|arg| arg == name)
+    }
+
+    /// Get the value of the last `key=value` argument
+    pub fn get_value(&self, key: &str) -> Option<&str> {
+        self.args()
+            .filter_map(|arg| arg.split_once('='))
+            .filter(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+            .last()
+    }
+}
+
+/// Split the first argument from `text`
+///
+/// # Result
+/// If an argument exists, Some((argument without quotes, the rest)), otherwise None
+fn split_first_arg(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start_matches(' ');
+    if text.is_empty() {
+        return None;
+    }
+    if let Some(quoted) = text.strip_prefix('"') {
+        match quoted.find('"') {
+            Some(end) => Some((&quoted[..end], &quoted[end + 1..])),
+            None => Some((quoted, &quoted[quoted.len()..])),
+        }
+    } else {
+        match text.find(' ') {
+            Some(end) => Some((&text[..end], &text[end..])),
+            None => Some((text, &text[text.len()..])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn to_load_options(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .chain([0])
+            .flat_map(|c| c.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn empty_load_options() {
+        for load_options in [Vec::new(), to_load_options(""), to_load_options("  ")] {
+            let command_line = CommandLine::from_load_options(&load_options);
+            assert!(command_line.is_empty());
+            assert_eq!(command_line.args().count(), 0);
+            assert_eq!(command_line.get_value("path"), None);
+            assert_eq!(command_line.as_bytes_with_nul(), b"\0");
+        }
+    }
+
+    #[test]
+    fn path_argument() {
+        let command_line = CommandLine::from_load_options(&to_load_options(
+            "\\EFI\\BOOT\\loader.efi debug path=\\EFI\\bv.elf\tquiet",
+        ));
+        assert_eq!(command_line.as_str(), "debug path=\\EFI\\bv.elf quiet");
+        assert_eq!(command_line.get_value("path"), Some("\\EFI\\bv.elf"));
+        assert!(command_line.has_switch("debug"));
+        assert!(command_line.has_switch("quiet"));
+
+        let command_line = CommandLine::from_load_options(&to_load_options(
+            "\"path=\\EFI\\Bit Visor\\bv.elf\" debug",
+        ));
+        assert_eq!(
+            command_line.get_value("path"),
+            Some("\\EFI\\Bit Visor\\bv.elf")
+        );
+        assert!(command_line.has_switch("debug"));
+
+        /* The last one is used */
+        let command_line =
+            CommandLine::from_load_options(&to_load_options("path=\\a.elf path=\\b.elf"));
+        assert_eq!(command_line.get_value("path"), Some("\\b.elf"));
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let command_line =
+            CommandLine::from_load_options(&to_load_options("unknown=1 path=\\bv.elf other"));
+        assert_eq!(command_line.get_value("path"), Some("\\bv.elf"));
+        assert_eq!(command_line.get_value("unknown"), Some("1"));
+        assert_eq!(command_line.get_value("missing"), None);
+        assert!(!command_line.has_switch("unknown"));
+        assert!(!command_line.has_switch("path"));
+    }
+
+    #[test]
+    fn not_utf16_text() {
+        /* Unpaired surrogate */
+        let load_options = [0x70, 0x00, 0x00, 0xd8, 0x61, 0x00];
+        assert!(CommandLine::from_load_options(&load_options).is_empty());
+        /* Binary OptionalData of Boot#### */
+        let load_options = [0x01, 0x00, 0x02, 0x00];
+        assert!(CommandLine::from_load_options(&load_options).is_empty());
+
+        /* The odd byte at the end is dropped */
+        let mut load_options = to_load_options("debug");
+        load_options.pop();
+        load_options.pop();
+        load_options.push(0x41);
+        let command_line = CommandLine::from_load_options(&load_options);
+        assert_eq!(command_line.as_str(), "debug");
+    }
+
+    #[test]
+    fn path_longer_than_menu_entry() {
+        use crate::menu::{BootMenu, MAX_PATH_LENGTH};
+        let long_path = "a".repeat(MAX_PATH_LENGTH + 1);
+        let command_line =
+            CommandLine::from_load_options(&to_load_options(&std::format!("path={}", long_path)));
+        let path = command_line.get_value("path").unwrap();
+        assert_eq!(path, long_path);
+        /* The loader ignores the option when the menu rejects it */
+        let mut menu = BootMenu::new();
+        assert!(!menu.add_entry(path.encode_utf16(), true));
+        assert!(menu.is_empty());
+    }
+
+    #[test]
+    fn long_path() {
+        let long_path = "a".repeat(MAX_COMMAND_LINE_LENGTH * 2);
+        let command_line =
+            CommandLine::from_load_options(&to_load_options(&std::format!("path={}", long_path)));
+        let path = command_line.get_value("path").unwrap();
+        assert_eq!(path.len(), MAX_COMMAND_LINE_LENGTH - 1 - "path=".len());
+        assert!(long_path.starts_with(path));
+        assert_eq!(
+            command_line.as_bytes_with_nul().len(),
+            MAX_COMMAND_LINE_LENGTH
+        );
+    }
+}
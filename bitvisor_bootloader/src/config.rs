@@ -0,0 +1,454 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Boot Configuration
+//!
+//! Parse `key = value` lines of the boot configuration file.
+//! Empty lines and the text after `#` are ignored.
+//!
+//! | Key                   | Value                                       |
+//! |-----------------------|---------------------------------------------|
+//! | `image`               | the path of the hypervisor image on the ESP |
+//! | `max_load_address`    | the upper border address to load the image  |
+//! | `bootstrap_size`      | the size passed as BitVisorBoot             |
+//! | `load_bsdriver`       | `false` to modify ACPI tables natively      |
+//! | `bsdriver_path`       | the path of bsdriver.efi on the ESP         |
+//! | `menu_image`          | another image listed in the boot menu       |
+//! | `menu_timeout`        | seconds to wait before booting the default  |
+//! | `prompt_timeout`      | seconds to wait for a key to skip BitVisor  |
+//! | `console`             | `text`, `serial` or `both`                  |
+//! | `serial_port`         | the I/O port of the UART, default is 0x3F8  |
+//! | `log`                 | the log levels like `info,loader=debug`     |
+//! | `save_boot_log`       | `false` not to write `bootlog.txt`          |
+//! | `framebuffer_console` | `true` to draw the text on the framebuffer  |
+//!
+//! `menu_image` can be written up to MAX_MENU_IMAGES times.
+//! `serial_port` is used only when EFI_SERIAL_IO_PROTOCOL is not found.
+//! `bsdriver_path` is used only when the loader is built with the `bsdriver-file` feature.
+//!
+
+use crate::console::ConsoleOutput;
+use crate::logger::LogFilter;
+use core::fmt;
+
+pub const MAX_IMAGE_PATH_LENGTH: usize = 255;
+pub const MAX_MENU_IMAGES: usize = 7;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConfigErrorKind {
+    NotUtf8,
+    MissingSeparator,
+    UnknownKey,
+    DuplicatedKey,
+    InvalidNumber,
+    InvalidBoolean,
+    InvalidPath,
+    InvalidConsole,
+    InvalidLogFilter,
+    TooManyImages,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConfigError {
+    /// The line number starting from 1
+    pub line: usize,
+    pub kind: ConfigErrorKind,
+}
+
+#[derive(Clone, Copy)]
+struct ConfigPath {
+    path: [u8; MAX_IMAGE_PATH_LENGTH],
+    length: usize,
+}
+
+impl ConfigPath {
+    const fn new() -> Self {
+        Self {
+            path: [0; MAX_IMAGE_PATH_LENGTH],
+            length: 0,
+        }
+    }
+
+    fn set(&mut self, value: &str) -> Result<(), ConfigErrorKind> {
+        if value.is_empty()
+            || value.len() > MAX_IMAGE_PATH_LENGTH
+            || !value.is_ascii()
+            || value.bytes().any(|c| c.is_ascii_control())
+        {
+            return Err(ConfigErrorKind::InvalidPath);
+        }
+        self.path[..value.len()].copy_from_slice(value.as_bytes());
+        self.length = value.len();
+        Ok(())
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        if self.length == 0 {
+            return None;
+        }
+        core::str::from_utf8(&self.path[..self.length]).ok()
+    }
+}
+
+impl fmt::Debug for ConfigPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_str(), f)
+    }
+}
+
+/// Settings of the boot configuration file
+///
+/// None means the key is not written, and the default is used.
+#[derive(Clone, Copy, Debug)]
+pub struct BootConfig {
+    image_path: ConfigPath,
+    menu_images: [ConfigPath; MAX_MENU_IMAGES],
+    num_of_menu_images: usize,
+    bsdriver_path: ConfigPath,
+    pub max_load_address: Option<usize>,
+    pub bootstrap_size: Option<usize>,
+    pub load_bsdriver: Option<bool>,
+    pub menu_timeout: Option<usize>,
+    pub prompt_timeout: Option<usize>,
+    pub console: Option<ConsoleOutput>,
+    pub serial_port: Option<u16>,
+    pub log_filter: Option<LogFilter>,
+    pub save_boot_log: Option<bool>,
+    pub framebuffer_console: Option<bool>,
+}
+
+impl BootConfig {
+    /// The configuration which has no settings
+    pub const fn new() -> Self {
+        Self {
+            image_path: ConfigPath::new(),
+            menu_images: [ConfigPath::new(); MAX_MENU_IMAGES],
+            num_of_menu_images: 0,
+            bsdriver_path: ConfigPath::new(),
+            max_load_address: None,
+            bootstrap_size: None,
+            load_bsdriver: None,
+            menu_timeout: None,
+            prompt_timeout: None,
+            console: None,
+            serial_port: None,
+            log_filter: None,
+            save_boot_log: None,
+            framebuffer_console: None,
+        }
+    }
+
+    /// Parse the contents of the configuration file
+    ///
+    /// # Arguments
+    /// * `text` - the whole contents of the file
+    ///
+    /// # Result
+    /// If all lines are valid, Ok(BootConfig), otherwise Err(ConfigError) of the first invalid line
+    pub fn parse(text: &[u8]) -> Result<Self, ConfigError> {
+        let text = core::str::from_utf8(text).map_err(|e| ConfigError {
+            line: text[..e.valid_up_to()]
+                .iter()
+                .filter(|&&c| c == b'\n')
+                .count()
+                + 1,
+            kind: ConfigErrorKind::NotUtf8,
+        })?;
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        let mut config = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            config.parse_line(line).map_err(|kind| ConfigError {
+                line: index + 1,
+                kind,
+            })?;
+        }
+        Ok(config)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), ConfigErrorKind> {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        }
+        .trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(ConfigErrorKind::MissingSeparator)?;
+        let (key, value) = (key.trim(), value.trim());
+        match key {
+            "image" => {
+                if self.image_path.length != 0 {
+                    return Err(ConfigErrorKind::DuplicatedKey);
+                }
+                self.image_path.set(value)?;
+            }
+            "menu_image" => {
+                if self.num_of_menu_images >= MAX_MENU_IMAGES {
+                    return Err(ConfigErrorKind::TooManyImages);
+                }
+                self.menu_images[self.num_of_menu_images].set(value)?;
+                self.num_of_menu_images += 1;
+            }
+            "bsdriver_path" => {
+                if self.bsdriver_path.length != 0 {
+                    return Err(ConfigErrorKind::DuplicatedKey);
+                }
+                self.bsdriver_path.set(value)?;
+            }
+            "max_load_address" => {
+                Self::set_once(&mut self.max_load_address, parse_number(value)?)?;
+            }
+            "bootstrap_size" => {
+                Self::set_once(&mut self.bootstrap_size, parse_number(value)?)?;
+            }
+            "load_bsdriver" => {
+                Self::set_once(&mut self.load_bsdriver, parse_boolean(value)?)?;
+            }
+            "menu_timeout" => {
+                Self::set_once(&mut self.menu_timeout, parse_number(value)?)?;
+            }
+            "prompt_timeout" => {
+                Self::set_once(&mut self.prompt_timeout, parse_number(value)?)?;
+            }
+            "console" => {
+                let console =
+                    ConsoleOutput::from_name(value).ok_or(ConfigErrorKind::InvalidConsole)?;
+                Self::set_once(&mut self.console, console)?;
+            }
+            "serial_port" => {
+                let port = u16::try_from(parse_number(value)?)
+                    .map_err(|_| ConfigErrorKind::InvalidNumber)?;
+                Self::set_once(&mut self.serial_port, port)?;
+            }
+            "log" => {
+                let log_filter =
+                    LogFilter::parse(value).ok_or(ConfigErrorKind::InvalidLogFilter)?;
+                Self::set_once(&mut self.log_filter, log_filter)?;
+            }
+            "save_boot_log" => {
+                Self::set_once(&mut self.save_boot_log, parse_boolean(value)?)?;
+            }
+            "framebuffer_console" => {
+                Self::set_once(&mut self.framebuffer_console, parse_boolean(value)?)?;
+            }
+            _ => return Err(ConfigErrorKind::UnknownKey),
+        }
+        Ok(())
+    }
+
+    fn set_once<T>(setting: &mut Option<T>, value: T) -> Result<(), ConfigErrorKind> {
+        if setting.is_some() {
+            return Err(ConfigErrorKind::DuplicatedKey);
+        }
+        *setting = Some(value);
+        Ok(())
+    }
+
+    /// Get the path of the hypervisor image from the root of the ESP
+    ///
+    /// Slashes are not converted, the path should be written with backslashes.
+    pub fn image_path(&self) -> Option<&str> {
+        self.image_path.as_str()
+    }
+
+    /// Get the path of bsdriver.efi from the root of the ESP
+    #[cfg(feature = "bsdriver-file")]
+    pub fn bsdriver_path(&self) -> Option<&str> {
+        self.bsdriver_path.as_str()
+    }
+
+    /// Iterate the paths of the other images listed in the boot menu
+    pub fn menu_images(&self) -> impl Iterator<Item = &str> {
+        self.menu_images[..self.num_of_menu_images]
+            .iter()
+            .filter_map(|p| p.as_str())
+    }
+}
+
+/// Parse a decimal or `0x` prefixed hexadecimal number
+fn parse_number(value: &str) -> Result<usize, ConfigErrorKind> {
+    let (digits, radix) = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => (hex, 16),
+        None => (value, 10),
+    };
+    if digits.is_empty() || digits.starts_with('+') {
+        return Err(ConfigErrorKind::InvalidNumber);
+    }
+    usize::from_str_radix(digits, radix).map_err(|_| ConfigErrorKind::InvalidNumber)
+}
+
+fn parse_boolean(value: &str) -> Result<bool, ConfigErrorKind> {
+    const TRUE_VALUES: [&str; 4] = ["true", "yes", "on", "1"];
+    const FALSE_VALUES: [&str; 4] = ["false", "no", "off", "0"];
+    if TRUE_VALUES.iter().any(|v| value.eq_ignore_ascii_case(v)) {
+        Ok(true)
+    } else if FALSE_VALUES.iter().any(|v| value.eq_ignore_ascii_case(v)) {
+        Ok(false)
+    } else {
+        Err(ConfigErrorKind::InvalidBoolean)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+    use std::vec::Vec;
+
+    fn parse_error(text: &str) -> ConfigError {
+        BootConfig::parse(text.as_bytes()).unwrap_err()
+    }
+
+    #[test]
+    fn empty_file() {
+        for text in ["", "\n\n", "\u{feff}", "  # only a comment\r\n"] {
+            let config = BootConfig::parse(text.as_bytes()).unwrap();
+            assert_eq!(config.image_path(), None);
+            assert_eq!(config.menu_images().count(), 0);
+            assert_eq!(config.max_load_address, None);
+            assert_eq!(config.load_bsdriver, None);
+        }
+    }
+
+    #[test]
+    fn all_keys() {
+        let config = BootConfig::parse(
+            b"image = \\EFI\\BOOT\\bitvisor.elf\n\
+              menu_image = \\EFI\\BOOT\\old.elf # fallback\n\
+              max_load_address = 0x40000000\n\
+              bootstrap_size=1048576\n\
+              load_bsdriver = No\n\
+              menu_timeout = 5\n\
+              serial_port = 0x2f8\n\
+              save_boot_log = off\n",
+        )
+        .unwrap();
+        assert_eq!(config.image_path(), Some("\\EFI\\BOOT\\bitvisor.elf"));
+        assert_eq!(
+            config.menu_images().collect::<Vec<_>>(),
+            ["\\EFI\\BOOT\\old.elf"]
+        );
+        assert_eq!(config.max_load_address, Some(0x40000000));
+        assert_eq!(config.bootstrap_size, Some(0x100000));
+        assert_eq!(config.load_bsdriver, Some(false));
+        assert_eq!(config.menu_timeout, Some(5));
+        assert_eq!(config.serial_port, Some(0x2f8));
+        assert_eq!(config.save_boot_log, Some(false));
+        assert_eq!(config.prompt_timeout, None);
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let config =
+            BootConfig::parse(b"\xef\xbb\xbfimage = \\bitvisor.elf\r\nmenu_timeout = 3\r\n\r\n")
+                .unwrap();
+        assert_eq!(config.image_path(), Some("\\bitvisor.elf"));
+        assert_eq!(config.menu_timeout, Some(3));
+    }
+
+    #[test]
+    fn unknown_key() {
+        assert_eq!(
+            parse_error("image = \\bitvisor.elf\nimage_path = \\bitvisor.elf\n"),
+            ConfigError {
+                line: 2,
+                kind: ConfigErrorKind::UnknownKey
+            }
+        );
+        assert_eq!(parse_error(" = 1\n").kind, ConfigErrorKind::UnknownKey);
+    }
+
+    #[test]
+    fn missing_separator() {
+        assert_eq!(
+            parse_error("# comment\r\n\r\nmenu_timeout 5\r\n"),
+            ConfigError {
+                line: 3,
+                kind: ConfigErrorKind::MissingSeparator
+            }
+        );
+        /* The separator inside a comment does not count */
+        assert_eq!(
+            parse_error("load_bsdriver # = true\n").kind,
+            ConfigErrorKind::MissingSeparator
+        );
+    }
+
+    #[test]
+    fn duplicated_key() {
+        for text in [
+            "image = \\a.elf\nimage = \\b.elf\n",
+            "menu_timeout = 1\nmenu_timeout = 1\n",
+            "load_bsdriver = true\nload_bsdriver = false\n",
+        ] {
+            assert_eq!(
+                parse_error(text),
+                ConfigError {
+                    line: 2,
+                    kind: ConfigErrorKind::DuplicatedKey
+                }
+            );
+        }
+        let mut text = String::new();
+        for i in 0..=MAX_MENU_IMAGES {
+            text += &std::format!("menu_image = \\{}.elf\n", i);
+        }
+        assert_eq!(
+            parse_error(&text),
+            ConfigError {
+                line: MAX_MENU_IMAGES + 1,
+                kind: ConfigErrorKind::TooManyImages
+            }
+        );
+    }
+
+    #[test]
+    fn path_length() {
+        let longest = "a".repeat(MAX_IMAGE_PATH_LENGTH);
+        let config = BootConfig::parse(std::format!("image = {}\n", longest).as_bytes()).unwrap();
+        assert_eq!(config.image_path(), Some(longest.as_str()));
+
+        let too_long = "a".repeat(MAX_IMAGE_PATH_LENGTH + 1);
+        for key in ["image", "menu_image", "bsdriver_path"] {
+            assert_eq!(
+                parse_error(&std::format!("{} = {}\n", key, too_long)).kind,
+                ConfigErrorKind::InvalidPath
+            );
+        }
+        assert_eq!(parse_error("image =\n").kind, ConfigErrorKind::InvalidPath);
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert_eq!(
+            parse_error("menu_timeout = +5\n").kind,
+            ConfigErrorKind::InvalidNumber
+        );
+        assert_eq!(
+            parse_error("serial_port = 0x10000\n").kind,
+            ConfigErrorKind::InvalidNumber
+        );
+        assert_eq!(
+            parse_error("load_bsdriver = maybe\n").kind,
+            ConfigErrorKind::InvalidBoolean
+        );
+        assert_eq!(
+            BootConfig::parse(b"menu_timeout = 1\n\xff\n").unwrap_err(),
+            ConfigError {
+                line: 2,
+                kind: ConfigErrorKind::NotUtf8
+            }
+        );
+    }
+}
@@ -6,16 +6,59 @@
 // http://opensource.org/licenses/mit-license.php
 
 //!
-//! Console with UEFI Output Protocol
+//! Console with UEFI Output Protocol and UEFI Input Protocol
+//!
+//! The output can be mirrored to the serial port, and it is always kept in the boot log.
+//! The framebuffer console replaces the text output when it is enabled or the text output fails.
 //!
 
-use crate::uefi::{output::EfiOutputProtocol, EfiStatus};
+use crate::bootlog;
+use crate::framebuffer::FramebufferConsole;
+use crate::serial::SerialPort;
+use crate::uefi::boot_service::{EfiBootServices, Event};
+use crate::uefi::input::{EfiInputKey, EfiInputProtocol};
+use crate::uefi::output::{efi_text_attr, EfiOutputProtocol};
+use crate::uefi::EfiStatus;
 
 use core::fmt;
 use core::mem::MaybeUninit;
+use core::ptr::{addr_of, addr_of_mut};
+
+/// The devices which the console writes to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConsoleOutput {
+    Text,
+    Serial,
+    Both,
+}
+
+impl ConsoleOutput {
+    /// Parse `text`, `serial` or `both`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Self::Text),
+            "serial" => Some(Self::Serial),
+            "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+
+    pub fn uses_text(&self) -> bool {
+        *self != Self::Serial
+    }
+
+    pub fn uses_serial(&self) -> bool {
+        *self != Self::Text
+    }
+}
 
 pub struct Console {
     uefi_output_console: MaybeUninit<&'static EfiOutputProtocol>,
+    uefi_input_console: Option<&'static EfiInputProtocol>,
+    serial_port: Option<SerialPort>,
+    output: ConsoleOutput,
+    framebuffer: Option<FramebufferConsole>,
+    is_framebuffer_active: bool,
     //write_lock: SpinLockFlag, // Currently, Bootloader runs only BSP. Therefore the lock is not necessary.
 }
 
@@ -25,18 +68,216 @@ impl Console {
     pub const fn new() -> Self {
         Self {
             uefi_output_console: MaybeUninit::uninit(),
+            uefi_input_console: None,
+            serial_port: None,
+            output: ConsoleOutput::Text,
+            framebuffer: None,
+            is_framebuffer_active: false,
         }
     }
 
     pub fn init(&mut self, efi_output_protocol: *const EfiOutputProtocol) {
         self.uefi_output_console = MaybeUninit::new(unsafe { &*efi_output_protocol });
     }
+
+    pub fn init_serial(&mut self, serial_port: SerialPort) {
+        self.serial_port = Some(serial_port);
+    }
+
+    /// Select the devices to write
+    ///
+    /// If the serial port is not initialized, the text output is used instead.
+    pub fn set_output(&mut self, output: ConsoleOutput) {
+        self.output = output;
+    }
+
+    pub fn init_framebuffer(&mut self, framebuffer: FramebufferConsole) {
+        self.framebuffer = Some(framebuffer);
+    }
+
+    /// Write to the framebuffer instead of the text output
+    ///
+    /// The screen is cleared when the framebuffer console starts.
+    ///
+    /// # Result
+    /// If the framebuffer console is initialized, true, otherwise false
+    pub fn activate_framebuffer(&mut self) -> bool {
+        let Some(framebuffer) = &mut self.framebuffer else {
+            return false;
+        };
+        if !self.is_framebuffer_active {
+            framebuffer.clear_screen();
+            self.is_framebuffer_active = true;
+        }
+        true
+    }
+
+    /// Get the devices to write
+    pub fn get_output(&self) -> ConsoleOutput {
+        match self.serial_port {
+            Some(_) => self.output,
+            None => ConsoleOutput::Text,
+        }
+    }
+
+    fn output(&self) -> &'static EfiOutputProtocol {
+        unsafe { self.uefi_output_console.assume_init() }
+    }
+
+    /// Set the colors of the following output
+    ///
+    /// # Arguments
+    /// * `foreground` - EFI_BLACK ~ EFI_WHITE
+    /// * `background` - EFI_BLACK ~ EFI_LIGHTGRAY
+    pub fn set_color(&mut self, foreground: usize, background: usize) -> EfiStatus {
+        self.set_attribute(efi_text_attr(foreground, background))
+    }
+
+    /// Get the current attribute to restore it by [`Console::set_attribute`]
+    pub fn get_attribute(&self) -> usize {
+        self.output().get_mode().attribute as usize
+    }
+
+    pub fn set_attribute(&mut self, attribute: usize) -> EfiStatus {
+        if let Some(framebuffer) = &mut self.framebuffer {
+            framebuffer.set_color(attribute & 0x0F, (attribute >> 4) & 0x07);
+        }
+        self.output().set_attribute(attribute)
+    }
+
+    pub fn clear_screen(&mut self) -> EfiStatus {
+        if let (true, Some(framebuffer)) = (self.is_framebuffer_active, &mut self.framebuffer) {
+            framebuffer.clear_screen();
+            return EfiStatus::EfiSuccess;
+        }
+        self.output().clear_screen()
+    }
+
+    pub fn set_cursor_position(&self, column: usize, row: usize) -> EfiStatus {
+        self.output().set_cursor_position(column, row)
+    }
+
+    /// Get the cursor position
+    ///
+    /// # Result
+    /// (column, row)
+    pub fn get_cursor_position(&self) -> (usize, usize) {
+        let mode = self.output().get_mode();
+        (mode.cursor_column as usize, mode.cursor_row as usize)
+    }
+
+    pub fn enable_cursor(&self, visible: bool) -> EfiStatus {
+        self.output().enable_cursor(visible)
+    }
+
+    /// Switch to the text mode which has the most characters
+    ///
+    /// The screen is cleared only when the mode is changed.
+    ///
+    /// # Result
+    /// If the mode is selected, Ok((columns, rows)), otherwise Err(EfiStatus)
+    pub fn set_largest_mode(&self) -> Result<(usize, usize), EfiStatus> {
+        let output = self.output();
+        let current_mode = output.get_mode().mode as usize;
+        let mut largest_mode = current_mode;
+        let mut largest_size = output.query_mode(current_mode)?;
+        for mode_number in 0..(output.get_mode().max_mode as usize) {
+            /* Some modes may be unsupported by the current display */
+            if let Ok((columns, rows)) = output.query_mode(mode_number) {
+                if columns * rows > largest_size.0 * largest_size.1 {
+                    largest_mode = mode_number;
+                    largest_size = (columns, rows);
+                }
+            }
+        }
+        if largest_mode != current_mode {
+            let status = output.set_mode(largest_mode);
+            if status != EfiStatus::EfiSuccess {
+                return Err(status);
+            }
+        }
+        Ok(largest_size)
+    }
+
+    pub fn init_input(&mut self, efi_input_protocol: *const EfiInputProtocol) {
+        if !efi_input_protocol.is_null() {
+            self.uefi_input_console = Some(unsafe { &*efi_input_protocol });
+        }
+    }
+
+    /// Wait for a key press
+    ///
+    /// # Arguments
+    /// * `b_s` - EfiBootService to create the timer
+    /// * `timeout_ms` - the time to wait in milliseconds, None waits forever
+    ///
+    /// # Result
+    /// If a key is pressed, Some(EfiInputKey).
+    /// If the timeout expires or the console has no input, None
+    pub fn wait_for_key(
+        &self,
+        b_s: &EfiBootServices,
+        timeout_ms: Option<u64>,
+    ) -> Option<EfiInputKey> {
+        let input = self.uefi_input_console?;
+        if let Ok(key) = input.read_key_stroke() {
+            return Some(key);
+        }
+        let timer = match timeout_ms {
+            Some(timeout_ms) => {
+                let timer = Event::new_timer(b_s).ok()?;
+                timer.set_relative(timeout_ms * 1000).ok()?;
+                Some(timer)
+            }
+            None => None,
+        };
+
+        let events = [
+            input.wait_for_key(),
+            timer.as_ref().map_or(0, |t| t.as_raw()),
+        ];
+        let num_of_events = if timer.is_some() { 2 } else { 1 };
+        loop {
+            match b_s.wait_for_events(&events[..num_of_events]) {
+                Ok(0) => {
+                    /* The event may be signaled without a key, then wait again */
+                    if let Ok(key) = input.read_key_stroke() {
+                        return Some(key);
+                    }
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Discard the keys pressed before
+    pub fn flush_input(&self) {
+        if let Some(input) = self.uefi_input_console {
+            let _ = input.reset(false);
+        }
+    }
 }
 
 impl fmt::Write for Console {
     fn write_str(&mut self, string: &str) -> fmt::Result {
-        let result = unsafe { self.uefi_output_console.assume_init().output(string) };
-        if result == EfiStatus::EfiSuccess {
+        bootlog::append(string.as_bytes());
+        let output = self.get_output();
+        let mut is_written = false;
+        if output.uses_text() {
+            if !self.is_framebuffer_active && self.output().output(string) == EfiStatus::EfiSuccess
+            {
+                is_written = true;
+            } else if self.activate_framebuffer() {
+                if let Some(framebuffer) = &mut self.framebuffer {
+                    framebuffer.write_str(string);
+                }
+                is_written = true;
+            }
+        }
+        if let (true, Some(serial_port)) = (output.uses_serial(), &self.serial_port) {
+            is_written |= serial_port.write_str(string).is_ok();
+        }
+        if is_written {
             Ok(())
         } else {
             Err(fmt::Error)
@@ -44,9 +285,29 @@ impl fmt::Write for Console {
     }
 }
 
+/// Wait for a key press on the default console
+///
+/// See [`Console::wait_for_key`].
+pub fn wait_for_key(b_s: &EfiBootServices, timeout_ms: Option<u64>) -> Option<EfiInputKey> {
+    unsafe { (*addr_of!(DEFAULT_CONSOLE)).wait_for_key(b_s, timeout_ms) }
+}
+
+/// Print with the foreground color, then restore the previous colors
+///
+/// # Arguments
+/// * `foreground` - EFI_BLACK ~ EFI_WHITE
+/// * `args` - the arguments made by format_args!
+pub fn print_with_color(foreground: usize, args: fmt::Arguments) {
+    let attribute = unsafe { (*addr_of!(DEFAULT_CONSOLE)).get_attribute() };
+    let background = (attribute >> 4) & 0x07;
+    unsafe { (*addr_of_mut!(DEFAULT_CONSOLE)).set_color(foreground, background) };
+    print(args);
+    unsafe { (*addr_of_mut!(DEFAULT_CONSOLE)).set_attribute(attribute) };
+}
+
 pub fn print(args: fmt::Arguments) {
     use fmt::Write;
-    let result = unsafe { DEFAULT_CONSOLE.write_fmt(args) };
+    let result = unsafe { (*addr_of_mut!(DEFAULT_CONSOLE)).write_fmt(args) };
     if result.is_err() {
         panic!("write_fmt was failed.");
     }
@@ -62,17 +323,3 @@ macro_rules! println {
     ($fmt:expr) => ($crate::console::print(format_args!("{}\n", format_args!($fmt))));
     ($fmt:expr, $($arg:tt)*) => ($crate::console::print(format_args!("{}\n", format_args!($fmt, $($arg)*))));
 }
-
-#[cfg(debug_assertions)]
-#[macro_export]
-macro_rules! pr_debug {
-    ($fmt:expr) => (println!($fmt));
-    ($fmt:expr, $($arg:tt)*) => (println!($fmt, $($arg)*));
-}
-
-#[cfg(not(debug_assertions))]
-#[macro_export]
-macro_rules! pr_debug {
-    ($fmt:expr) => {};
-    ($fmt:expr, $($arg:tt)*) => {};
-}
\ No newline at end of file
@@ -3,8 +3,38 @@ use core::arch::asm;
 /// Halt cpu
 /// stop the cpu
 #[inline(always)]
+#[cfg_attr(test, allow(dead_code))]
 pub fn halt_loop() -> ! {
     loop {
         unsafe { asm!("hlt")};
     }
-}
\ No newline at end of file
+}
+
+/// Write a byte to the I/O port
+#[inline(always)]
+pub fn out_byte(port: u16, data: u8) {
+    unsafe { asm!("out dx, al", in("dx") port, in("al") data) };
+}
+
+/// Read a byte from the I/O port
+#[inline(always)]
+pub fn in_byte(port: u16) -> u8 {
+    let data: u8;
+    unsafe { asm!("in al, dx", in("dx") port, out("al") data) };
+    data
+}
+
+/// Read the Time Stamp Counter
+#[inline(always)]
+pub fn read_tsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Check if the TSC runs at a constant rate regardless of the power state
+///
+/// CPUID.80000007H:EDX[8] is the invariant TSC bit.
+pub fn has_invariant_tsc() -> bool {
+    use core::arch::x86_64::__cpuid;
+    let max_extended_leaf = __cpuid(0x80000000).eax;
+    max_extended_leaf >= 0x80000007 && (__cpuid(0x80000007).edx & (1 << 8)) != 0
+}
@@ -0,0 +1,727 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Compressed Image Decoder
+//!
+//! Detect a compressed container around the hypervisor image by its magic bytes and expand it.
+//! Supported format: gzip (RFC 1952) with DEFLATE (RFC 1951)
+//!
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const GZIP_METHOD_DEFLATE: u8 = 8;
+const GZIP_HEADER_SIZE: usize = 10;
+const GZIP_TRAILER_SIZE: usize = 8;
+const GZIP_FLAG_HCRC: u8 = 1 << 1;
+const GZIP_FLAG_EXTRA: u8 = 1 << 2;
+const GZIP_FLAG_NAME: u8 = 1 << 3;
+const GZIP_FLAG_COMMENT: u8 = 1 << 4;
+const GZIP_FLAG_RESERVED: u8 = 0xe0;
+
+/// Upper bound of the DEFLATE expansion: one 258-byte match per 2 bits
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+const MAX_CODE_BITS: usize = 15;
+const MAX_LITERAL_LENGTH_CODES: usize = 288;
+const MAX_DISTANCE_CODES: usize = 30;
+const NUM_OF_CODE_LENGTH_CODES: usize = 19;
+const END_OF_BLOCK: u16 = 256;
+
+const CODE_LENGTH_ORDER: [usize; NUM_OF_CODE_LENGTH_CODES] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; MAX_DISTANCE_CODES] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; MAX_DISTANCE_CODES] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const CRC32_TABLE: [u32; 256] = make_crc32_table();
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CompressionFormat {
+    Gzip,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecompressError {
+    TooSmall,
+    InvalidHeader,
+    UnsupportedMethod(u8),
+    UnexpectedEnd,
+    InvalidBlockType,
+    InvalidStoredBlockLength,
+    InvalidHuffmanCode,
+    InvalidDistance(usize),
+    OutputTooSmall,
+    InvalidSize(usize),
+    SizeMismatch { expected: usize, actual: usize },
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+/// Detect the compression format by the magic bytes
+///
+/// # Result
+/// If `data` starts with a supported magic, Some(CompressionFormat), otherwise None
+pub fn detect_compression(data: &[u8]) -> Option<CompressionFormat> {
+    if data.len() >= GZIP_HEADER_SIZE + GZIP_TRAILER_SIZE && data[..GZIP_MAGIC.len()] == GZIP_MAGIC
+    {
+        return Some(CompressionFormat::Gzip);
+    }
+    None
+}
+
+/// Get the size of the expanded data recorded in the container
+///
+/// The gzip format records the size modulo 2^32, so images must be smaller than 4GiB.
+/// The recorded size is not trusted beyond what the compressed data can expand to,
+/// and [`decompress`] verifies it against the bytes actually decoded.
+///
+/// # Result
+/// If the container is valid, Ok(size), otherwise Err(DecompressError)
+pub fn get_decompressed_size(
+    format: CompressionFormat,
+    data: &[u8],
+) -> Result<usize, DecompressError> {
+    match format {
+        CompressionFormat::Gzip => {
+            if data.len() < GZIP_HEADER_SIZE + GZIP_TRAILER_SIZE {
+                return Err(DecompressError::TooSmall);
+            }
+            let size = read_u32(data, data.len() - 4) as usize;
+            let max_size = (data.len() - GZIP_HEADER_SIZE - GZIP_TRAILER_SIZE)
+                .saturating_mul(MAX_DEFLATE_RATIO);
+            if size > max_size {
+                return Err(DecompressError::InvalidSize(size));
+            }
+            Ok(size)
+        }
+    }
+}
+
+/// Expand `input` into `output`
+///
+/// # Arguments
+/// * `format` - the format detected by [`detect_compression`]
+/// * `input` - the whole compressed data
+/// * `output` - the buffer to store the expanded data
+///
+/// # Result
+/// If the data is expanded and verified, Ok(expanded_size), otherwise Err(DecompressError)
+pub fn decompress(
+    format: CompressionFormat,
+    input: &[u8],
+    output: &mut [u8],
+) -> Result<usize, DecompressError> {
+    match format {
+        CompressionFormat::Gzip => decompress_gzip(input, output),
+    }
+}
+
+fn decompress_gzip(input: &[u8], output: &mut [u8]) -> Result<usize, DecompressError> {
+    if input.len() < GZIP_HEADER_SIZE + GZIP_TRAILER_SIZE {
+        return Err(DecompressError::TooSmall);
+    }
+    if input[..GZIP_MAGIC.len()] != GZIP_MAGIC {
+        return Err(DecompressError::InvalidHeader);
+    }
+    if input[2] != GZIP_METHOD_DEFLATE {
+        return Err(DecompressError::UnsupportedMethod(input[2]));
+    }
+    let flags = input[3];
+    if (flags & GZIP_FLAG_RESERVED) != 0 {
+        return Err(DecompressError::InvalidHeader);
+    }
+
+    let mut position = GZIP_HEADER_SIZE;
+    if (flags & GZIP_FLAG_EXTRA) != 0 {
+        let extra_length = input
+            .get(position..position + 2)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        position += 2 + u16::from_le_bytes([extra_length[0], extra_length[1]]) as usize;
+    }
+    for flag in [GZIP_FLAG_NAME, GZIP_FLAG_COMMENT] {
+        if (flags & flag) != 0 {
+            let length = input
+                .get(position..)
+                .and_then(|s| s.iter().position(|&c| c == 0))
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            position += length + 1;
+        }
+    }
+    if (flags & GZIP_FLAG_HCRC) != 0 {
+        position += 2;
+    }
+    if position > input.len() {
+        return Err(DecompressError::UnexpectedEnd);
+    }
+
+    let mut reader = BitReader::new(&input[position..]);
+    let size = inflate(&mut reader, output)?;
+
+    let trailer = position + reader.get_consumed_bytes();
+    if trailer + GZIP_TRAILER_SIZE > input.len() {
+        return Err(DecompressError::UnexpectedEnd);
+    }
+    let expected_size = read_u32(input, trailer + 4) as usize;
+    if expected_size != (size & (u32::MAX as usize)) {
+        return Err(DecompressError::SizeMismatch {
+            expected: expected_size,
+            actual: size,
+        });
+    }
+    let expected_crc = read_u32(input, trailer);
+    let actual_crc = crc32(&output[..size]);
+    if expected_crc != actual_crc {
+        return Err(DecompressError::ChecksumMismatch {
+            expected: expected_crc,
+            actual: actual_crc,
+        });
+    }
+    Ok(size)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+const fn make_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if (c & 1) != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &b| {
+        CRC32_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// LSB-first bit reader of DEFLATE stream
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn read_bits(&mut self, bits: u32) -> Result<u32, DecompressError> {
+        while self.bit_count < bits {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            self.position += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u32 << bits) - 1);
+        self.bit_buffer >>= bits;
+        self.bit_count -= bits;
+        Ok(value)
+    }
+
+    /// Discard the remaining bits of the current byte
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+
+    fn get_consumed_bytes(&self) -> usize {
+        self.position
+    }
+}
+
+/// Canonical Huffman code table
+struct Huffman<const N: usize> {
+    counts: [u16; MAX_CODE_BITS + 1],
+    symbols: [u16; N],
+}
+
+impl<const N: usize> Huffman<N> {
+    const fn new() -> Self {
+        Self {
+            counts: [0; MAX_CODE_BITS + 1],
+            symbols: [0; N],
+        }
+    }
+
+    /// Build the table from the code length of each symbol
+    ///
+    /// Incomplete codes are accepted because encoders emit them for a single distance code.
+    fn construct(&mut self, lengths: &[u8]) -> Result<(), DecompressError> {
+        self.counts = [0; MAX_CODE_BITS + 1];
+        for &length in lengths {
+            self.counts[length as usize] += 1;
+        }
+        let mut left: i32 = 1;
+        for length in 1..=MAX_CODE_BITS {
+            left <<= 1;
+            left -= self.counts[length] as i32;
+            if left < 0 {
+                return Err(DecompressError::InvalidHuffmanCode);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_BITS + 1];
+        for length in 1..MAX_CODE_BITS {
+            offsets[length + 1] = offsets[length] + self.counts[length];
+        }
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                self.symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, DecompressError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_CODE_BITS {
+            code |= reader.read_bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(DecompressError::InvalidHuffmanCode)
+    }
+}
+
+/// Expand the raw DEFLATE stream
+///
+/// # Result
+/// If the stream is expanded until the final block, Ok(expanded_size), otherwise Err(DecompressError)
+fn inflate(reader: &mut BitReader, output: &mut [u8]) -> Result<usize, DecompressError> {
+    let mut output_position = 0;
+    let mut literal_length_code = Huffman::<MAX_LITERAL_LENGTH_CODES>::new();
+    let mut distance_code = Huffman::<MAX_DISTANCE_CODES>::new();
+    loop {
+        let is_final = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => {
+                output_position = inflate_stored(reader, output, output_position)?;
+            }
+            1 => {
+                build_fixed_codes(&mut literal_length_code, &mut distance_code)?;
+                output_position = inflate_codes(
+                    reader,
+                    output,
+                    output_position,
+                    &literal_length_code,
+                    &distance_code,
+                )?;
+            }
+            2 => {
+                build_dynamic_codes(reader, &mut literal_length_code, &mut distance_code)?;
+                output_position = inflate_codes(
+                    reader,
+                    output,
+                    output_position,
+                    &literal_length_code,
+                    &distance_code,
+                )?;
+            }
+            _ => return Err(DecompressError::InvalidBlockType),
+        }
+        if is_final {
+            return Ok(output_position);
+        }
+    }
+}
+
+fn inflate_stored(
+    reader: &mut BitReader,
+    output: &mut [u8],
+    output_position: usize,
+) -> Result<usize, DecompressError> {
+    reader.align_to_byte();
+    let length = reader.read_bits(16)? as usize;
+    let complement = reader.read_bits(16)? as usize;
+    if length != (!complement & 0xffff) {
+        return Err(DecompressError::InvalidStoredBlockLength);
+    }
+    let start = reader.position;
+    let source = reader
+        .data
+        .get(start..start + length)
+        .ok_or(DecompressError::UnexpectedEnd)?;
+    output
+        .get_mut(output_position..output_position + length)
+        .ok_or(DecompressError::OutputTooSmall)?
+        .copy_from_slice(source);
+    reader.position += length;
+    Ok(output_position + length)
+}
+
+fn build_fixed_codes(
+    literal_length_code: &mut Huffman<MAX_LITERAL_LENGTH_CODES>,
+    distance_code: &mut Huffman<MAX_DISTANCE_CODES>,
+) -> Result<(), DecompressError> {
+    let mut lengths = [0u8; MAX_LITERAL_LENGTH_CODES];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    literal_length_code.construct(&lengths)?;
+    distance_code.construct(&[5; MAX_DISTANCE_CODES])
+}
+
+fn build_dynamic_codes(
+    reader: &mut BitReader,
+    literal_length_code: &mut Huffman<MAX_LITERAL_LENGTH_CODES>,
+    distance_code: &mut Huffman<MAX_DISTANCE_CODES>,
+) -> Result<(), DecompressError> {
+    let num_of_literal_length_codes = reader.read_bits(5)? as usize + 257;
+    let num_of_distance_codes = reader.read_bits(5)? as usize + 1;
+    let num_of_code_length_codes = reader.read_bits(4)? as usize + 4;
+    if num_of_literal_length_codes > 286 || num_of_distance_codes > MAX_DISTANCE_CODES {
+        return Err(DecompressError::InvalidHuffmanCode);
+    }
+
+    let mut code_lengths = [0u8; NUM_OF_CODE_LENGTH_CODES];
+    for &symbol in &CODE_LENGTH_ORDER[..num_of_code_length_codes] {
+        code_lengths[symbol] = reader.read_bits(3)? as u8;
+    }
+    let mut code_length_code = Huffman::<NUM_OF_CODE_LENGTH_CODES>::new();
+    code_length_code.construct(&code_lengths)?;
+
+    let total = num_of_literal_length_codes + num_of_distance_codes;
+    let mut lengths = [0u8; MAX_LITERAL_LENGTH_CODES + MAX_DISTANCE_CODES];
+    let mut index = 0;
+    while index < total {
+        let symbol = code_length_code.decode(reader)?;
+        let (length, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if index == 0 {
+                    return Err(DecompressError::InvalidHuffmanCode);
+                }
+                (lengths[index - 1], 3 + reader.read_bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.read_bits(3)? as usize),
+            _ => (0, 11 + reader.read_bits(7)? as usize),
+        };
+        if index + repeat > total {
+            return Err(DecompressError::InvalidHuffmanCode);
+        }
+        lengths[index..index + repeat].fill(length);
+        index += repeat;
+    }
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(DecompressError::InvalidHuffmanCode);
+    }
+
+    literal_length_code.construct(&lengths[..num_of_literal_length_codes])?;
+    distance_code.construct(&lengths[num_of_literal_length_codes..total])
+}
+
+fn inflate_codes(
+    reader: &mut BitReader,
+    output: &mut [u8],
+    mut output_position: usize,
+    literal_length_code: &Huffman<MAX_LITERAL_LENGTH_CODES>,
+    distance_code: &Huffman<MAX_DISTANCE_CODES>,
+) -> Result<usize, DecompressError> {
+    loop {
+        let symbol = literal_length_code.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            *output
+                .get_mut(output_position)
+                .ok_or(DecompressError::OutputTooSmall)? = symbol as u8;
+            output_position += 1;
+            continue;
+        } else if symbol == END_OF_BLOCK {
+            return Ok(output_position);
+        }
+
+        let symbol = (symbol - END_OF_BLOCK - 1) as usize;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(DecompressError::InvalidHuffmanCode);
+        }
+        let length = LENGTH_BASE[symbol] as usize
+            + reader.read_bits(LENGTH_EXTRA_BITS[symbol] as u32)? as usize;
+
+        let symbol = distance_code.decode(reader)? as usize;
+        if symbol >= MAX_DISTANCE_CODES {
+            return Err(DecompressError::InvalidHuffmanCode);
+        }
+        let distance = DISTANCE_BASE[symbol] as usize
+            + reader.read_bits(DISTANCE_EXTRA_BITS[symbol] as u32)? as usize;
+        if distance > output_position {
+            return Err(DecompressError::InvalidDistance(distance));
+        }
+        if output_position + length > output.len() {
+            return Err(DecompressError::OutputTooSmall);
+        }
+        /* The source may overlap the destination, so copy byte by byte */
+        for i in output_position..output_position + length {
+            output[i] = output[i - distance];
+        }
+        output_position += length;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::format;
+    use std::vec;
+    use std::vec::Vec;
+
+    const PLAIN_TEXT: &[u8] = b"BitVisor boot loader test data\n";
+
+    /// `PLAIN_TEXT` compressed with level 0 (a single stored block)
+    const STORED_GZIP: [u8; 54] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x1f, 0x00, 0xe0, 0xff,
+        0x42, 0x69, 0x74, 0x56, 0x69, 0x73, 0x6f, 0x72, 0x20, 0x62, 0x6f, 0x6f, 0x74, 0x20, 0x6c,
+        0x6f, 0x61, 0x64, 0x65, 0x72, 0x20, 0x74, 0x65, 0x73, 0x74, 0x20, 0x64, 0x61, 0x74, 0x61,
+        0x0a, 0xa1, 0x3e, 0xe7, 0xfa, 0x1f, 0x00, 0x00, 0x00,
+    ];
+
+    /// `PLAIN_TEXT` compressed with Z_FIXED (a single fixed Huffman block)
+    const FIXED_GZIP: [u8; 51] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x73, 0xca, 0x2c, 0x09, 0xcb,
+        0x2c, 0xce, 0x2f, 0x52, 0x48, 0xca, 0xcf, 0x2f, 0x51, 0xc8, 0xc9, 0x4f, 0x4c, 0x49, 0x2d,
+        0x52, 0x28, 0x49, 0x2d, 0x2e, 0x51, 0x48, 0x49, 0x2c, 0x49, 0xe4, 0x02, 0x00, 0xa1, 0x3e,
+        0xe7, 0xfa, 0x1f, 0x00, 0x00, 0x00,
+    ];
+
+    /// [`dynamic_plain_text`] compressed with level 9 (a single dynamic Huffman block)
+    const DYNAMIC_GZIP: [u8; 119] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x55, 0xd0, 0xb9, 0x0d, 0x80,
+        0x30, 0x14, 0x04, 0xd1, 0x9c, 0x2a, 0x5c, 0x82, 0x6f, 0x9b, 0x72, 0x0c, 0x36, 0x24, 0x1c,
+        0x01, 0x04, 0x94, 0x0f, 0x48, 0x48, 0xfc, 0xc9, 0x56, 0xfb, 0xb2, 0x39, 0xda, 0xbc, 0xb6,
+        0xed, 0x54, 0x5a, 0x2d, 0x7b, 0xa9, 0xad, 0xaa, 0xf2, 0xec, 0x4b, 0x77, 0xc7, 0xf7, 0x1b,
+        0xfc, 0x46, 0xeb, 0x9f, 0x2c, 0xc8, 0x4a, 0x72, 0x20, 0x27, 0xc9, 0x83, 0xbc, 0xa4, 0x00,
+        0x0a, 0x92, 0x22, 0x28, 0x4a, 0x4a, 0xa0, 0x24, 0x29, 0x83, 0xb2, 0xa4, 0x1e, 0xd4, 0x4b,
+        0x32, 0x6c, 0x51, 0x60, 0xec, 0x31, 0xc0, 0x18, 0x64, 0x84, 0xb1, 0x48, 0x85, 0x31, 0x49,
+        0x83, 0xb1, 0xc9, 0xf4, 0xda, 0x0d, 0x00, 0x0f, 0x33, 0xe3, 0xb3, 0x01, 0x00, 0x00,
+    ];
+
+    fn dynamic_plain_text() -> Vec<u8> {
+        (0..16)
+            .flat_map(|i| format!("segment {} loaded at {:#x}\n", i, 0x1000 * i).into_bytes())
+            .collect()
+    }
+
+    /// Expand `input` through the same path as the loader
+    fn expand(input: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        let format = detect_compression(input).ok_or(DecompressError::InvalidHeader)?;
+        let size = get_decompressed_size(format, input)?;
+        let mut output = vec![0u8; size];
+        let expanded = decompress(format, input, &mut output)?;
+        output.truncate(expanded);
+        Ok(output)
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!((STORED_GZIP[GZIP_HEADER_SIZE] >> 1) & 3, 0);
+        assert_eq!(expand(&STORED_GZIP).unwrap(), PLAIN_TEXT);
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        assert_eq!((FIXED_GZIP[GZIP_HEADER_SIZE] >> 1) & 3, 1);
+        assert_eq!(expand(&FIXED_GZIP).unwrap(), PLAIN_TEXT);
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        assert_eq!((DYNAMIC_GZIP[GZIP_HEADER_SIZE] >> 1) & 3, 2);
+        assert_eq!(expand(&DYNAMIC_GZIP).unwrap(), dynamic_plain_text());
+    }
+
+    /// Compress `data` with the system gzip
+    fn gzip(data: &[u8], level: &str) -> Vec<u8> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+        let mut child = Command::new("gzip")
+            .args(["-c", "-n", level])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("gzip is not found");
+        let mut stdin = child.stdin.take().unwrap();
+        let data = data.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&data).unwrap());
+        let output = child.wait_with_output().unwrap();
+        writer.join().unwrap();
+        assert!(output.status.success());
+        output.stdout
+    }
+
+    /// Text followed by pseudo-random bytes, larger than the 32KiB window
+    fn round_trip_payload() -> Vec<u8> {
+        let mut payload = dynamic_plain_text().repeat(200);
+        let mut seed = 0x1234_5678u32;
+        payload.extend((0..0x20000).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        }));
+        payload
+    }
+
+    #[test]
+    fn round_trip_with_gzip() {
+        let payload = round_trip_payload();
+        for level in ["-1", "-6", "-9"] {
+            let input = gzip(&payload, level);
+            assert_eq!(
+                get_decompressed_size(CompressionFormat::Gzip, &input),
+                Ok(payload.len())
+            );
+            assert_eq!(expand(&input).unwrap(), payload, "gzip {}", level);
+        }
+    }
+
+    #[test]
+    fn corrupted_crc_is_rejected() {
+        let payload = round_trip_payload();
+        let mut input = gzip(&payload, "-9");
+        let crc_offset = input.len() - GZIP_TRAILER_SIZE;
+        let actual = crc32(&payload);
+        assert_eq!(read_u32(&input, crc_offset), actual);
+        input[crc_offset] ^= 0xff;
+        assert_eq!(
+            expand(&input),
+            Err(DecompressError::ChecksumMismatch {
+                expected: actual ^ 0xff,
+                actual
+            })
+        );
+    }
+
+    #[test]
+    fn corrupted_data_is_rejected() {
+        let mut input = STORED_GZIP;
+        input[GZIP_HEADER_SIZE + 5] ^= 0x01;
+        let mut corrupted = PLAIN_TEXT.to_vec();
+        corrupted[0] ^= 0x01;
+        assert_eq!(
+            expand(&input),
+            Err(DecompressError::ChecksumMismatch {
+                expected: crc32(PLAIN_TEXT),
+                actual: crc32(&corrupted)
+            })
+        );
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        let round_trip = gzip(&round_trip_payload(), "-9");
+        for input in [
+            &STORED_GZIP[..],
+            &FIXED_GZIP[..],
+            &DYNAMIC_GZIP[..],
+            &round_trip[..],
+        ] {
+            let mut output = vec![0u8; 0x40000];
+            for length in (0..input.len()).step_by(input.len() / 64 + 1) {
+                let expected = if length < GZIP_HEADER_SIZE + GZIP_TRAILER_SIZE {
+                    DecompressError::TooSmall
+                } else {
+                    DecompressError::UnexpectedEnd
+                };
+                assert_eq!(
+                    decompress(CompressionFormat::Gzip, &input[..length], &mut output),
+                    Err(expected),
+                    "{} bytes",
+                    length
+                );
+            }
+            /* The stream is complete but the trailer is cut */
+            assert_eq!(
+                decompress(
+                    CompressionFormat::Gzip,
+                    &input[..input.len() - 1],
+                    &mut output
+                ),
+                Err(DecompressError::UnexpectedEnd)
+            );
+            assert_eq!(
+                expand(&input[..GZIP_HEADER_SIZE]),
+                Err(DecompressError::InvalidHeader)
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_length_field_is_rejected() {
+        let mut input = FIXED_GZIP;
+        let size_offset = input.len() - 4;
+        input[size_offset..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            get_decompressed_size(CompressionFormat::Gzip, &input),
+            Err(DecompressError::InvalidSize(u32::MAX as usize))
+        );
+    }
+
+    #[test]
+    fn mismatched_length_field_is_rejected() {
+        let mut input = FIXED_GZIP;
+        let size_offset = input.len() - 4;
+        input[size_offset..].copy_from_slice(&(PLAIN_TEXT.len() as u32 + 1).to_le_bytes());
+        assert!(matches!(
+            expand(&input),
+            Err(DecompressError::SizeMismatch { .. })
+        ));
+        input[size_offset..].copy_from_slice(&(PLAIN_TEXT.len() as u32 - 1).to_le_bytes());
+        assert_eq!(expand(&input), Err(DecompressError::OutputTooSmall));
+    }
+}
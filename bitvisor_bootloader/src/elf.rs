@@ -12,11 +12,12 @@
 //!
 //! Supported Version: 1
 
-#[allow(dead_code)]
-
-use crate::println;
+use core::mem::size_of;
 
-const EI_NIDENT: usize = 16;
+pub const EI_NIDENT: usize = 16;
+const EI_CLASS: usize = 4;
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
 pub const ELF32_IDENTIFIER: [u8; EI_NIDENT] = [
     0x7f, 0x45, 0x4c, 0x46, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
@@ -26,23 +27,43 @@ pub const ELF64_IDENTIFIER: [u8; EI_NIDENT] = [
 const EM_AARCH64: Elf64Half = 183;
 const EM_X86_64:Elf64Half = 62;
 const EM_386: Elf32Half = 3; // Intel 80386
-const PT_LOAD: Elf64Word = 1;
+const ET_DYN: Elf64Half = 3;
+pub const PT_LOAD: Elf64Word = 1;
+pub const PT_DYNAMIC: Elf64Word = 2;
+pub const PT_NOTE: Elf64Word = 4;
+const SHT_SYMTAB: Elf64Word = 2;
+const SHT_NOBITS: Elf64Word = 8;
+const SHT_DYNSYM: Elf64Word = 11;
+const DT_NULL: Elf64Sxword = 0;
+const DT_RELA: Elf64Sxword = 7;
+const DT_RELASZ: Elf64Sxword = 8;
+const DT_RELAENT: Elf64Sxword = 9;
+const DT_REL: Elf64Sxword = 17;
+const DT_RELSZ: Elf64Sxword = 18;
+const DT_RELENT: Elf64Sxword = 19;
+pub const R_NONE: u32 = 0;
+const R_386_RELATIVE: u32 = 8;
+const R_X86_64_RELATIVE: u32 = 8;
+const R_AARCH64_RELATIVE: u32 = 1027;
+const SHN_UNDEF: Elf64Half = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
 const ELF_VERSION: Elf64Word = 0x1;
 
 type Elf32Addr = u32;
 type Elf32Half = u16;
 type Elf32Off = u32;
-//type Elf32Sword = i32;
+type Elf32Sword = i32;
 //type Elf32Sxword = i64;
 type Elf32Word = u32;
-type Elf32Xword = u64;
+//type Elf32Xword = u64;
 //type Elf32Section = u16;
 
 type Elf64Addr = u64;
 type Elf64Half = u16;
 type Elf64Off = u64;
 //type Elf64Sword = i32;
-//type Elf64Sxword = i64;
+type Elf64Sxword = i64;
 type Elf64Word = u32;
 type Elf64Xword = u64;
 //type Elf32Section = u16;
@@ -107,6 +128,135 @@ struct Elf64ProgramHeader {
     p_align: Elf64Xword,
 }
 
+#[repr(C)]
+struct Elf32SectionHeader {
+    sh_name: Elf32Word,      /* Section name (string tbl index) */
+    sh_type: Elf32Word,      /* Section type */
+    sh_flags: Elf32Word,     /* Section flags */
+    sh_addr: Elf32Addr,      /* Section virtual addr at execution */
+    sh_offset: Elf32Off,     /* Section file offset */
+    sh_size: Elf32Word,      /* Section size in bytes */
+    sh_link: Elf32Word,      /* Link to another section */
+    sh_info: Elf32Word,      /* Additional section information */
+    sh_addralign: Elf32Word, /* Section alignment */
+    sh_entsize: Elf32Word,   /* Entry size if section holds table */
+}
+
+#[repr(C)]
+struct Elf64SectionHeader {
+    sh_name: Elf64Word,
+    sh_type: Elf64Word,
+    sh_flags: Elf64Xword,
+    sh_addr: Elf64Addr,
+    sh_offset: Elf64Off,
+    sh_size: Elf64Xword,
+    sh_link: Elf64Word,
+    sh_info: Elf64Word,
+    sh_addralign: Elf64Xword,
+    sh_entsize: Elf64Xword,
+}
+
+#[repr(C)]
+struct Elf32Symbol {
+    st_name: Elf32Word,  /* Symbol name (string tbl index) */
+    st_value: Elf32Addr, /* Symbol value */
+    st_size: Elf32Word,  /* Symbol size */
+    st_info: u8,         /* Symbol type and binding */
+    st_other: u8,        /* Symbol visibility */
+    st_shndx: Elf32Half, /* Section index */
+}
+
+#[repr(C)]
+struct Elf64Symbol {
+    st_name: Elf64Word,
+    st_info: u8,
+    st_other: u8,
+    st_shndx: Elf64Half,
+    st_value: Elf64Addr,
+    st_size: Elf64Xword,
+}
+
+#[repr(C)]
+struct Elf32Rel {
+    r_offset: Elf32Addr, /* Address */
+    r_info: Elf32Word,   /* Relocation type and symbol index */
+}
+
+#[repr(C)]
+struct Elf32Rela {
+    r_offset: Elf32Addr,  /* Address */
+    r_info: Elf32Word,    /* Relocation type and symbol index */
+    r_addend: Elf32Sword, /* Addend */
+}
+
+#[repr(C)]
+struct Elf64Rel {
+    r_offset: Elf64Addr,
+    r_info: Elf64Xword,
+}
+
+#[repr(C)]
+struct Elf64Rela {
+    r_offset: Elf64Addr,
+    r_info: Elf64Xword,
+    r_addend: Elf64Sxword,
+}
+
+#[repr(C)]
+struct Elf32Dynamic {
+    d_tag: Elf32Sword, /* Dynamic entry type */
+    d_val: Elf32Word,  /* Integer or address value */
+}
+
+#[repr(C)]
+struct Elf64Dynamic {
+    d_tag: Elf64Sxword,
+    d_val: Elf64Xword,
+}
+
+/// Both ELF32 and ELF64 use 4-byte words for the note header
+#[repr(C)]
+struct ElfNoteHeader {
+    n_namesz: Elf64Word, /* Length of the note's name */
+    n_descsz: Elf64Word, /* Length of the note's descriptor */
+    n_type: Elf64Word,   /* Type of the note */
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ElfClass {
+    Elf32,
+    Elf64,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ElfError {
+    /// The buffer is smaller than the ELF header
+    TooSmall,
+    InvalidIdentifier([u8; EI_NIDENT]),
+    UnsupportedMachine(u16),
+    UnsupportedVersion(u32),
+    /// `e_phoff`, `e_phentsize` or `e_phnum` points outside of the buffer
+    InvalidProgramHeaderTable,
+    /// The segment of the index points outside of the buffer
+    InvalidSegment(usize),
+    /// PT_DYNAMIC is broken
+    InvalidDynamicSegment,
+    /// The relocation table points outside of the buffer
+    InvalidRelocationTable,
+}
+
+/// Detect the ELF class from `e_ident[EI_CLASS]`
+///
+/// # Result
+/// If the class is ELFCLASS32 or ELFCLASS64, Some(ElfClass), otherwise None
+pub fn get_elf_class(e_ident: &[u8; EI_NIDENT]) -> Option<ElfClass> {
+    match e_ident[EI_CLASS] {
+        ELFCLASS32 => Some(ElfClass::Elf32),
+        ELFCLASS64 => Some(ElfClass::Elf64),
+        _ => None,
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct SegmentInfo {
@@ -115,26 +265,122 @@ pub struct SegmentInfo {
     pub file_offset: usize,
     pub memory_size: usize,
     pub file_size: usize,
+    pub alignment: usize,
     pub readable: bool,
     pub writable: bool,
     pub executable: bool,
 }
 
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct SectionInfo {
+    pub name_offset: usize,
+    pub section_type: u32,
+    pub flags: usize,
+    pub address: usize,
+    pub file_offset: usize,
+    pub size: usize,
+    pub link: usize,
+    pub info: usize,
+    pub alignment: usize,
+    pub entry_size: usize,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct SymbolInfo<'a> {
+    pub name: &'a str,
+    pub value: usize,
+    pub size: usize,
+    pub symbol_type: u8,
+    pub section_index: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct RelocationInfo {
+    /// The virtual address to patch
+    pub offset: usize,
+    pub relocation_type: u32,
+    pub symbol_index: usize,
+    /// The explicit addend of Rela, Rel has no addend
+    pub addend: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NoteInfo<'a> {
+    /// The owner name without the terminating NUL
+    pub name: &'a [u8],
+    pub note_type: u32,
+    pub descriptor: &'a [u8],
+}
+
+struct NoteIterator<'a> {
+    data: &'a [u8],
+    offset: usize,
+    alignment: usize,
+}
+
+impl<'a> Iterator for NoteIterator<'a> {
+    type Item = NoteInfo<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mask = self.alignment - 1;
+        let align_up = |x: usize| x.checked_add(mask).map(|x| x & !mask);
+        let header: ElfNoteHeader = read_struct(self.data, self.offset)?;
+        let name_offset = self.offset + size_of::<ElfNoteHeader>();
+        let name_end = name_offset.checked_add(header.n_namesz as usize)?;
+        let descriptor_offset = align_up(name_end)?;
+        let descriptor_end = descriptor_offset.checked_add(header.n_descsz as usize)?;
+        let name = self.data.get(name_offset..name_end)?;
+        let descriptor = self.data.get(descriptor_offset..descriptor_end)?;
+        self.offset = align_up(descriptor_end)?;
+        Some(NoteInfo {
+            name: name.strip_suffix(&[0]).unwrap_or(name),
+            note_type: header.n_type,
+            descriptor,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RelocationTable {
+    file_offset: usize,
+    size: usize,
+    entry_size: usize,
+    with_addend: bool,
+}
+
+/// Read `T` at `offset` of `data` after checking the range
+///
+/// `T` must be a plain old data type like the ELF headers
+fn read_struct<T>(data: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(size_of::<T>())?;
+    if end > data.len() {
+        return None;
+    }
+    Some(unsafe { core::ptr::read_unaligned(data.as_ptr().add(offset) as *const T) })
+}
+
 impl Elf32Header {
-    pub fn check_elf_header(&self) -> bool {
+    pub fn check_elf_header(&self) -> Result<(), ElfError> {
         if self.e_ident != ELF32_IDENTIFIER {
-            println!("Invalid elf identifier: {:?}", self.e_ident);
-            return false;
+            return Err(ElfError::InvalidIdentifier(self.e_ident));
         }
         if self.e_machine != EM_386 {
-            println!("Target machine is not matched: {}", self.e_machine);
-            return false;
+            return Err(ElfError::UnsupportedMachine(self.e_machine));
         }
         if self.e_version < ELF_VERSION {
-            println!("Unsupported ELF version: {}", self.e_version);
-            return false;
+            return Err(ElfError::UnsupportedVersion(self.e_version));
         }
-        true
+        Ok(())
+    }
+
+    pub fn get_machine(&self) -> u16 {
+        self.e_machine
+    }
+
+    pub fn is_position_independent(&self) -> bool {
+        self.e_type == ET_DYN
     }
 
     pub fn get_entry_point(&self) -> usize {
@@ -153,28 +399,36 @@ impl Elf32Header {
         self.e_phentsize as usize
     }
 
-    pub fn get_segment_info(
-        &self,
-        index: usize,
-        program_header_base: usize,
-    ) -> Option<SegmentInfo> {
-        if self.get_num_of_program_header_entries() <= index {
-            return None;
-        }
-        let program_header = unsafe {
-            &*((program_header_base + index * (self.e_phentsize as usize))
-                as *const Elf64ProgramHeader)
-        };
-        if program_header.p_type == PT_LOAD {
+    pub fn get_section_header_offset(&self) -> usize {
+        self.e_shoff as usize
+    }
+
+    pub fn get_num_of_section_header_entries(&self) -> usize {
+        self.e_shnum as usize
+    }
+
+    pub fn get_section_header_entry_size(&self) -> usize {
+        self.e_shentsize as usize
+    }
+
+    pub fn get_section_name_table_index(&self) -> usize {
+        self.e_shstrndx as usize
+    }
+}
+
+impl Elf32ProgramHeader {
+    fn get_segment_info(&self, segment_type: u32) -> Option<SegmentInfo> {
+        if self.p_type == segment_type {
             Some(SegmentInfo {
-                file_offset: program_header.p_offset as usize,
-                virtual_base_address: program_header.p_vaddr as usize,
-                physical_base_address: program_header.p_paddr as usize,
-                memory_size: program_header.p_memsz as usize,
-                file_size: program_header.p_filesz as usize,
-                readable: (program_header.p_flags & 0x4) != 0,
-                writable: (program_header.p_flags & 0x2) != 0,
-                executable: (program_header.p_flags & 0x1) != 0,
+                file_offset: self.p_offset as usize,
+                virtual_base_address: self.p_vaddr as usize,
+                physical_base_address: self.p_paddr as usize,
+                memory_size: self.p_memsz as usize,
+                file_size: self.p_filesz as usize,
+                alignment: self.p_align as usize,
+                readable: (self.p_flags & 0x4) != 0,
+                writable: (self.p_flags & 0x2) != 0,
+                executable: (self.p_flags & 0x1) != 0,
             })
         } else {
             None
@@ -182,21 +436,55 @@ impl Elf32Header {
     }
 }
 
+impl Elf32SectionHeader {
+    fn get_section_info(&self) -> SectionInfo {
+        SectionInfo {
+            name_offset: self.sh_name as usize,
+            section_type: self.sh_type,
+            flags: self.sh_flags as usize,
+            address: self.sh_addr as usize,
+            file_offset: self.sh_offset as usize,
+            size: self.sh_size as usize,
+            link: self.sh_link as usize,
+            info: self.sh_info as usize,
+            alignment: self.sh_addralign as usize,
+            entry_size: self.sh_entsize as usize,
+        }
+    }
+}
+
+impl Elf32Symbol {
+    fn get_symbol_info<'a>(&self, name: &'a str) -> SymbolInfo<'a> {
+        SymbolInfo {
+            name,
+            value: self.st_value as usize,
+            size: self.st_size as usize,
+            symbol_type: self.st_info & 0xf,
+            section_index: self.st_shndx,
+        }
+    }
+}
+
 impl Elf64Header {
-    pub fn check_elf_header(&self) -> bool {
+    pub fn check_elf_header(&self) -> Result<(), ElfError> {
         if self.e_ident != ELF64_IDENTIFIER {
-            println!("Invalid elf identifier: {:?}", self.e_ident);
-            return false;
+            return Err(ElfError::InvalidIdentifier(self.e_ident));
         }
         if self.e_machine != EM_X86_64 && self.e_machine != EM_AARCH64 {
-            println!("Target machine is not matched: {}", self.e_machine);
-            return false;
+            return Err(ElfError::UnsupportedMachine(self.e_machine));
         }
         if self.e_version < ELF_VERSION {
-            println!("Unsupported ELF version: {}", self.e_version);
-            return false;
+            return Err(ElfError::UnsupportedVersion(self.e_version));
         }
-        true
+        Ok(())
+    }
+
+    pub fn get_machine(&self) -> u16 {
+        self.e_machine
+    }
+
+    pub fn is_position_independent(&self) -> bool {
+        self.e_type == ET_DYN
     }
 
     pub fn get_entry_point(&self) -> usize {
@@ -215,31 +503,791 @@ impl Elf64Header {
         self.e_phentsize as usize
     }
 
-    pub fn get_segment_info(
-        &self,
-        index: usize,
-        program_header_base: usize,
-    ) -> Option<SegmentInfo> {
+    pub fn get_section_header_offset(&self) -> usize {
+        self.e_shoff as usize
+    }
+
+    pub fn get_num_of_section_header_entries(&self) -> usize {
+        self.e_shnum as usize
+    }
+
+    pub fn get_section_header_entry_size(&self) -> usize {
+        self.e_shentsize as usize
+    }
+
+    pub fn get_section_name_table_index(&self) -> usize {
+        self.e_shstrndx as usize
+    }
+}
+
+impl Elf64ProgramHeader {
+    fn get_segment_info(&self, segment_type: u32) -> Option<SegmentInfo> {
+        if self.p_type == segment_type {
+            Some(SegmentInfo {
+                file_offset: self.p_offset as usize,
+                virtual_base_address: self.p_vaddr as usize,
+                physical_base_address: self.p_paddr as usize,
+                memory_size: self.p_memsz as usize,
+                file_size: self.p_filesz as usize,
+                alignment: self.p_align as usize,
+                readable: (self.p_flags & 0x4) != 0,
+                writable: (self.p_flags & 0x2) != 0,
+                executable: (self.p_flags & 0x1) != 0,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl Elf64SectionHeader {
+    fn get_section_info(&self) -> SectionInfo {
+        SectionInfo {
+            name_offset: self.sh_name as usize,
+            section_type: self.sh_type,
+            flags: self.sh_flags as usize,
+            address: self.sh_addr as usize,
+            file_offset: self.sh_offset as usize,
+            size: self.sh_size as usize,
+            link: self.sh_link as usize,
+            info: self.sh_info as usize,
+            alignment: self.sh_addralign as usize,
+            entry_size: self.sh_entsize as usize,
+        }
+    }
+}
+
+impl Elf64Symbol {
+    fn get_symbol_info<'a>(&self, name: &'a str) -> SymbolInfo<'a> {
+        SymbolInfo {
+            name,
+            value: self.st_value as usize,
+            size: self.st_size as usize,
+            symbol_type: self.st_info & 0xf,
+            section_index: self.st_shndx,
+        }
+    }
+}
+
+enum ElfHeader {
+    Elf32(Elf32Header),
+    Elf64(Elf64Header),
+}
+
+/// ELF image on the memory
+///
+/// The program headers are validated against the buffer in [`ElfFile::new`],
+/// the section headers are checked on each access because they are optional for loading.
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    header: ElfHeader,
+}
+
+impl<'a> ElfFile<'a> {
+    /// Parse the ELF image
+    ///
+    /// # Arguments
+    /// * `data` - the whole ELF image
+    ///
+    /// # Result
+    /// If the headers and PT_LOAD segments are inside of `data`, Ok(ElfFile), otherwise Err(ElfError)
+    pub fn new(data: &'a [u8]) -> Result<Self, ElfError> {
+        let e_ident: [u8; EI_NIDENT] = read_struct(data, 0).ok_or(ElfError::TooSmall)?;
+        let header = match get_elf_class(&e_ident) {
+            Some(ElfClass::Elf32) => {
+                let header: Elf32Header = read_struct(data, 0).ok_or(ElfError::TooSmall)?;
+                header.check_elf_header()?;
+                ElfHeader::Elf32(header)
+            }
+            Some(ElfClass::Elf64) => {
+                let header: Elf64Header = read_struct(data, 0).ok_or(ElfError::TooSmall)?;
+                header.check_elf_header()?;
+                ElfHeader::Elf64(header)
+            }
+            None => return Err(ElfError::InvalidIdentifier(e_ident)),
+        };
+        let elf_file = Self { data, header };
+        elf_file.validate_program_headers()?;
+        Ok(elf_file)
+    }
+
+    fn validate_program_headers(&self) -> Result<(), ElfError> {
+        let entry_size = self.get_program_header_entry_size();
+        let minimum_entry_size = match self.header {
+            ElfHeader::Elf32(_) => size_of::<Elf32ProgramHeader>(),
+            ElfHeader::Elf64(_) => size_of::<Elf64ProgramHeader>(),
+        };
+        if self.get_num_of_program_header_entries() == 0 {
+            return Ok(());
+        }
+        if entry_size < minimum_entry_size {
+            return Err(ElfError::InvalidProgramHeaderTable);
+        }
+        let table_end = entry_size
+            .checked_mul(self.get_num_of_program_header_entries())
+            .and_then(|s| s.checked_add(self.get_program_header_offset()))
+            .ok_or(ElfError::InvalidProgramHeaderTable)?;
+        if table_end > self.data.len() {
+            return Err(ElfError::InvalidProgramHeaderTable);
+        }
+
+        for index in 0..self.get_num_of_program_header_entries() {
+            let Some(segment) = self.get_segment_info(index) else {
+                continue;
+            };
+            let is_valid = segment.file_size <= segment.memory_size
+                && segment
+                    .file_offset
+                    .checked_add(segment.file_size)
+                    .is_some_and(|end| end <= self.data.len())
+                && segment
+                    .virtual_base_address
+                    .checked_add(segment.memory_size)
+                    .is_some()
+                && segment
+                    .physical_base_address
+                    .checked_add(segment.memory_size)
+                    .is_some();
+            if !is_valid {
+                return Err(ElfError::InvalidSegment(index));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_class(&self) -> ElfClass {
+        match self.header {
+            ElfHeader::Elf32(_) => ElfClass::Elf32,
+            ElfHeader::Elf64(_) => ElfClass::Elf64,
+        }
+    }
+
+    pub fn get_entry_point(&self) -> usize {
+        match &self.header {
+            ElfHeader::Elf32(h) => h.get_entry_point(),
+            ElfHeader::Elf64(h) => h.get_entry_point(),
+        }
+    }
+
+    pub fn get_num_of_program_header_entries(&self) -> usize {
+        match &self.header {
+            ElfHeader::Elf32(h) => h.get_num_of_program_header_entries(),
+            ElfHeader::Elf64(h) => h.get_num_of_program_header_entries(),
+        }
+    }
+
+    pub fn get_program_header_offset(&self) -> usize {
+        match &self.header {
+            ElfHeader::Elf32(h) => h.get_program_header_offset(),
+            ElfHeader::Elf64(h) => h.get_program_header_offset(),
+        }
+    }
+
+    pub fn get_program_header_entry_size(&self) -> usize {
+        match &self.header {
+            ElfHeader::Elf32(h) => h.get_program_header_entry_size(),
+            ElfHeader::Elf64(h) => h.get_program_header_entry_size(),
+        }
+    }
+
+    pub fn get_machine(&self) -> u16 {
+        match &self.header {
+            ElfHeader::Elf32(h) => h.get_machine(),
+            ElfHeader::Elf64(h) => h.get_machine(),
+        }
+    }
+
+    /// Whether the image is ET_DYN, which can be loaded at any address with relocations
+    pub fn is_position_independent(&self) -> bool {
+        match &self.header {
+            ElfHeader::Elf32(h) => h.is_position_independent(),
+            ElfHeader::Elf64(h) => h.is_position_independent(),
+        }
+    }
+
+    /// Get the PT_LOAD segment of the program header `index`
+    ///
+    /// # Result
+    /// If the entry exists and its type is PT_LOAD, Some(SegmentInfo), otherwise None
+    pub fn get_segment_info(&self, index: usize) -> Option<SegmentInfo> {
+        self.get_segment_info_by_type(index, PT_LOAD)
+    }
+
+    /// Get the segment of the program header `index` whose type is `segment_type`
+    ///
+    /// # Result
+    /// If the entry exists and its type is `segment_type`, Some(SegmentInfo), otherwise None
+    pub fn get_segment_info_by_type(&self, index: usize, segment_type: u32) -> Option<SegmentInfo> {
         if self.get_num_of_program_header_entries() <= index {
             return None;
         }
-        let program_header = unsafe {
-            &*((program_header_base + index * (self.e_phentsize as usize))
-                as *const Elf64ProgramHeader)
+        let offset = index
+            .checked_mul(self.get_program_header_entry_size())?
+            .checked_add(self.get_program_header_offset())?;
+        match self.header {
+            ElfHeader::Elf32(_) => {
+                read_struct::<Elf32ProgramHeader>(self.data, offset)?.get_segment_info(segment_type)
+            }
+            ElfHeader::Elf64(_) => {
+                read_struct::<Elf64ProgramHeader>(self.data, offset)?.get_segment_info(segment_type)
+            }
+        }
+    }
+
+    /// Find the first segment whose type is `segment_type`
+    pub fn find_segment_by_type(&self, segment_type: u32) -> Option<SegmentInfo> {
+        (0..self.get_num_of_program_header_entries())
+            .find_map(|i| self.get_segment_info_by_type(i, segment_type))
+    }
+
+    /// Convert the virtual address to the file offset with PT_LOAD segments
+    fn virtual_to_file_offset(&self, virtual_address: usize) -> Option<usize> {
+        (0..self.get_num_of_program_header_entries())
+            .filter_map(|i| self.get_segment_info(i))
+            .find(|s| {
+                s.virtual_base_address <= virtual_address
+                    && virtual_address - s.virtual_base_address < s.file_size
+            })
+            .map(|s| s.file_offset + (virtual_address - s.virtual_base_address))
+    }
+
+    /// Get the file contents of the segment
+    ///
+    /// # Result
+    /// If the segment is inside of the image, Some(data), otherwise None
+    pub fn get_segment_data(&self, segment: &SegmentInfo) -> Option<&'a [u8]> {
+        let end = segment.file_offset.checked_add(segment.file_size)?;
+        self.data.get(segment.file_offset..end)
+    }
+
+    pub fn get_num_of_section_header_entries(&self) -> usize {
+        match &self.header {
+            ElfHeader::Elf32(h) => h.get_num_of_section_header_entries(),
+            ElfHeader::Elf64(h) => h.get_num_of_section_header_entries(),
+        }
+    }
+
+    /// Get the section header of `index`
+    ///
+    /// # Result
+    /// If the entry is inside of the image, Some(SectionInfo), otherwise None
+    pub fn get_section_info(&self, index: usize) -> Option<SectionInfo> {
+        if self.get_num_of_section_header_entries() <= index {
+            return None;
+        }
+        let (offset, entry_size, minimum_entry_size) = match &self.header {
+            ElfHeader::Elf32(h) => (
+                h.get_section_header_offset(),
+                h.get_section_header_entry_size(),
+                size_of::<Elf32SectionHeader>(),
+            ),
+            ElfHeader::Elf64(h) => (
+                h.get_section_header_offset(),
+                h.get_section_header_entry_size(),
+                size_of::<Elf64SectionHeader>(),
+            ),
         };
-        if program_header.p_type == PT_LOAD {
-            Some(SegmentInfo {
-                file_offset: program_header.p_offset as usize,
-                virtual_base_address: program_header.p_vaddr as usize,
-                physical_base_address: program_header.p_paddr as usize,
-                memory_size: program_header.p_memsz as usize,
-                file_size: program_header.p_filesz as usize,
-                readable: (program_header.p_flags & 0x4) != 0,
-                writable: (program_header.p_flags & 0x2) != 0,
-                executable: (program_header.p_flags & 0x1) != 0,
+        if entry_size < minimum_entry_size {
+            return None;
+        }
+        let offset = entry_size
+            .checked_mul(index)
+            .and_then(|o| o.checked_add(offset))?;
+        match self.header {
+            ElfHeader::Elf32(_) => {
+                Some(read_struct::<Elf32SectionHeader>(self.data, offset)?.get_section_info())
+            }
+            ElfHeader::Elf64(_) => {
+                Some(read_struct::<Elf64SectionHeader>(self.data, offset)?.get_section_info())
+            }
+        }
+    }
+
+    /// Get the contents of the section
+    ///
+    /// # Result
+    /// If the section is inside of the image, Some(data), otherwise None.
+    /// SHT_NOBITS sections return an empty slice.
+    pub fn get_section_data(&self, section: &SectionInfo) -> Option<&'a [u8]> {
+        if section.section_type == SHT_NOBITS {
+            return Some(&[]);
+        }
+        let end = section.file_offset.checked_add(section.size)?;
+        self.data.get(section.file_offset..end)
+    }
+
+    /// Get the name of the section from the section header string table
+    pub fn get_section_name(&self, section: &SectionInfo) -> Option<&'a str> {
+        let index = match &self.header {
+            ElfHeader::Elf32(h) => h.get_section_name_table_index(),
+            ElfHeader::Elf64(h) => h.get_section_name_table_index(),
+        };
+        let string_table = self.get_section_info(index)?;
+        self.get_string(&string_table, section.name_offset)
+    }
+
+    /// Find the section by the name like ".symtab"
+    pub fn find_section_by_name(&self, name: &str) -> Option<SectionInfo> {
+        (0..self.get_num_of_section_header_entries())
+            .filter_map(|i| self.get_section_info(i))
+            .find(|s| self.get_section_name(s) == Some(name))
+    }
+
+    /// Read the NUL terminated string at `offset` of the string table
+    fn get_string(&self, string_table: &SectionInfo, offset: usize) -> Option<&'a str> {
+        let table = self.get_section_data(string_table)?;
+        let string = table.get(offset..)?;
+        let length = string.iter().position(|c| *c == 0)?;
+        core::str::from_utf8(&string[..length]).ok()
+    }
+
+    /// Get the symbol of `index` in the symbol table section
+    ///
+    /// # Result
+    /// If the entry and its name are inside of the image, Some(SymbolInfo), otherwise None
+    pub fn get_symbol_info(
+        &self,
+        symbol_table: &SectionInfo,
+        index: usize,
+    ) -> Option<SymbolInfo<'a>> {
+        let minimum_entry_size = match self.header {
+            ElfHeader::Elf32(_) => size_of::<Elf32Symbol>(),
+            ElfHeader::Elf64(_) => size_of::<Elf64Symbol>(),
+        };
+        if symbol_table.entry_size < minimum_entry_size
+            || symbol_table.size / symbol_table.entry_size <= index
+        {
+            return None;
+        }
+        let table = self.get_section_data(symbol_table)?;
+        let offset = index * symbol_table.entry_size;
+        let string_table = self.get_section_info(symbol_table.link)?;
+        match self.header {
+            ElfHeader::Elf32(_) => {
+                let symbol = read_struct::<Elf32Symbol>(table, offset)?;
+                let name = self.get_string(&string_table, symbol.st_name as usize)?;
+                Some(symbol.get_symbol_info(name))
+            }
+            ElfHeader::Elf64(_) => {
+                let symbol = read_struct::<Elf64Symbol>(table, offset)?;
+                let name = self.get_string(&string_table, symbol.st_name as usize)?;
+                Some(symbol.get_symbol_info(name))
+            }
+        }
+    }
+
+    /// Iterate the symbols of .symtab, then .dynsym
+    fn symbols(&self) -> impl Iterator<Item = SymbolInfo<'a>> + '_ {
+        [SHT_SYMTAB, SHT_DYNSYM]
+            .into_iter()
+            .flat_map(move |section_type| {
+                (0..self.get_num_of_section_header_entries())
+                    .filter_map(|i| self.get_section_info(i))
+                    .filter(move |s| s.section_type == section_type)
             })
+            .flat_map(move |table| {
+                let num_of_entries = table.size.checked_div(table.entry_size).unwrap_or(0);
+                (0..num_of_entries).filter_map(move |i| self.get_symbol_info(&table, i))
+            })
+    }
+
+    /// Find the defined symbol by the name in .symtab or .dynsym
+    #[allow(dead_code)]
+    pub fn find_symbol(&self, name: &str) -> Option<SymbolInfo<'a>> {
+        self.symbols()
+            .find(|s| s.section_index != SHN_UNDEF && s.name == name)
+    }
+
+    /// Find the function or object symbol which contains `virtual_address`
+    ///
+    /// # Result
+    /// If found, Some((SymbolInfo, offset from the symbol)), otherwise None
+    pub fn find_symbol_by_address(
+        &self,
+        virtual_address: usize,
+    ) -> Option<(SymbolInfo<'a>, usize)> {
+        self.symbols()
+            .filter(|s| {
+                s.section_index != SHN_UNDEF
+                    && (s.symbol_type == STT_FUNC || s.symbol_type == STT_OBJECT)
+                    && s.value <= virtual_address
+                    && (virtual_address - s.value < s.size
+                        || (s.size == 0 && virtual_address == s.value))
+            })
+            .map(|s| {
+                let offset = virtual_address - s.value;
+                (s, offset)
+            })
+            .next()
+    }
+
+    /// Get the type of R_*_RELATIVE for the machine of the image
+    pub fn get_relative_relocation_type(&self) -> Option<u32> {
+        match self.get_machine() {
+            EM_386 => Some(R_386_RELATIVE),
+            EM_X86_64 => Some(R_X86_64_RELATIVE),
+            EM_AARCH64 => Some(R_AARCH64_RELATIVE),
+            _ => None,
+        }
+    }
+
+    /// Find the Rel and Rela tables from PT_DYNAMIC, or from .rel.dyn/.rela.dyn sections
+    fn get_relocation_tables(&self) -> Result<[Option<RelocationTable>; 2], ElfError> {
+        let mut tables = [None, None];
+        if let Some(dynamic) = self.find_segment_by_type(PT_DYNAMIC) {
+            let data = self
+                .get_segment_data(&dynamic)
+                .ok_or(ElfError::InvalidDynamicSegment)?;
+            let entry_size = match self.header {
+                ElfHeader::Elf32(_) => size_of::<Elf32Dynamic>(),
+                ElfHeader::Elf64(_) => size_of::<Elf64Dynamic>(),
+            };
+            let (mut rel, mut rel_size, mut rel_entry_size) = (None, 0, 0);
+            let (mut rela, mut rela_size, mut rela_entry_size) = (None, 0, 0);
+            for offset in (0..data.len()).step_by(entry_size) {
+                let (tag, value) = match self.header {
+                    ElfHeader::Elf32(_) => {
+                        let d = read_struct::<Elf32Dynamic>(data, offset)
+                            .ok_or(ElfError::InvalidDynamicSegment)?;
+                        (d.d_tag as Elf64Sxword, d.d_val as usize)
+                    }
+                    ElfHeader::Elf64(_) => {
+                        let d = read_struct::<Elf64Dynamic>(data, offset)
+                            .ok_or(ElfError::InvalidDynamicSegment)?;
+                        (d.d_tag, d.d_val as usize)
+                    }
+                };
+                match tag {
+                    DT_NULL => break,
+                    DT_REL => rel = Some(value),
+                    DT_RELSZ => rel_size = value,
+                    DT_RELENT => rel_entry_size = value,
+                    DT_RELA => rela = Some(value),
+                    DT_RELASZ => rela_size = value,
+                    DT_RELAENT => rela_entry_size = value,
+                    _ => {}
+                }
+            }
+            for (i, (address, size, entry_size, with_addend)) in [
+                (rel, rel_size, rel_entry_size, false),
+                (rela, rela_size, rela_entry_size, true),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                if let Some(address) = address {
+                    tables[i] = Some(RelocationTable {
+                        file_offset: self
+                            .virtual_to_file_offset(address)
+                            .ok_or(ElfError::InvalidDynamicSegment)?,
+                        size,
+                        entry_size,
+                        with_addend,
+                    });
+                }
+            }
         } else {
-            None
+            for (i, (name, with_addend)) in [(".rel.dyn", false), (".rela.dyn", true)]
+                .into_iter()
+                .enumerate()
+            {
+                if let Some(section) = self.find_section_by_name(name) {
+                    tables[i] = Some(RelocationTable {
+                        file_offset: section.file_offset,
+                        size: section.size,
+                        entry_size: section.entry_size,
+                        with_addend,
+                    });
+                }
+            }
+        }
+
+        for table in tables.iter().flatten() {
+            let minimum_entry_size = match (&self.header, table.with_addend) {
+                (ElfHeader::Elf32(_), false) => size_of::<Elf32Rel>(),
+                (ElfHeader::Elf32(_), true) => size_of::<Elf32Rela>(),
+                (ElfHeader::Elf64(_), false) => size_of::<Elf64Rel>(),
+                (ElfHeader::Elf64(_), true) => size_of::<Elf64Rela>(),
+            };
+            if table.entry_size < minimum_entry_size
+                || !table
+                    .file_offset
+                    .checked_add(table.size)
+                    .is_some_and(|end| end <= self.data.len())
+            {
+                return Err(ElfError::InvalidRelocationTable);
+            }
+        }
+        Ok(tables)
+    }
+
+    fn get_relocation_info(&self, table: &RelocationTable, index: usize) -> Option<RelocationInfo> {
+        let offset = table.file_offset + index * table.entry_size;
+        match (&self.header, table.with_addend) {
+            (ElfHeader::Elf32(_), false) => {
+                let r = read_struct::<Elf32Rel>(self.data, offset)?;
+                Some(RelocationInfo {
+                    offset: r.r_offset as usize,
+                    relocation_type: r.r_info & 0xff,
+                    symbol_index: (r.r_info >> 8) as usize,
+                    addend: None,
+                })
+            }
+            (ElfHeader::Elf32(_), true) => {
+                let r = read_struct::<Elf32Rela>(self.data, offset)?;
+                Some(RelocationInfo {
+                    offset: r.r_offset as usize,
+                    relocation_type: r.r_info & 0xff,
+                    symbol_index: (r.r_info >> 8) as usize,
+                    addend: Some(r.r_addend as i64),
+                })
+            }
+            (ElfHeader::Elf64(_), false) => {
+                let r = read_struct::<Elf64Rel>(self.data, offset)?;
+                Some(RelocationInfo {
+                    offset: r.r_offset as usize,
+                    relocation_type: r.r_info as u32,
+                    symbol_index: (r.r_info >> 32) as usize,
+                    addend: None,
+                })
+            }
+            (ElfHeader::Elf64(_), true) => {
+                let r = read_struct::<Elf64Rela>(self.data, offset)?;
+                Some(RelocationInfo {
+                    offset: r.r_offset as usize,
+                    relocation_type: r.r_info as u32,
+                    symbol_index: (r.r_info >> 32) as usize,
+                    addend: Some(r.r_addend),
+                })
+            }
+        }
+    }
+
+    /// Iterate the dynamic relocations of the image
+    ///
+    /// # Result
+    /// If the relocation tables are inside of the image, Ok(iterator), otherwise Err(ElfError)
+    pub fn relocations(&self) -> Result<impl Iterator<Item = RelocationInfo> + '_, ElfError> {
+        let tables = self.get_relocation_tables()?;
+        Ok(tables.into_iter().flatten().flat_map(move |table| {
+            (0..table.size / table.entry_size)
+                .filter_map(move |i| self.get_relocation_info(&table, i))
+        }))
+    }
+
+    /// Iterate the notes in all PT_NOTE segments
+    ///
+    /// The iteration of a segment stops at the first broken note.
+    pub fn notes(&self) -> impl Iterator<Item = NoteInfo<'a>> + '_ {
+        (0..self.get_num_of_program_header_entries())
+            .filter_map(|i| self.get_segment_info_by_type(i, PT_NOTE))
+            .filter_map(|segment| {
+                Some(NoteIterator {
+                    data: self.get_segment_data(&segment)?,
+                    offset: 0,
+                    alignment: if segment.alignment == 8 { 8 } else { 4 },
+                })
+            })
+            .flatten()
+    }
+
+    /// Find the note by the owner name and the type
+    pub fn find_note(&self, name: &[u8], note_type: u32) -> Option<NoteInfo<'a>> {
+        self.notes()
+            .find(|n| n.name == name && n.note_type == note_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::string::String;
+    use std::vec::Vec;
+
+    /// A program header printed by `readelf -lW`
+    struct ReadelfSegment {
+        segment_type: String,
+        offset: usize,
+        virtual_address: usize,
+        physical_address: usize,
+        file_size: usize,
+        memory_size: usize,
+        flags: String,
+        alignment: usize,
+    }
+
+    fn parse_hex(s: &str) -> usize {
+        usize::from_str_radix(s.trim_start_matches("0x"), 16).unwrap()
+    }
+
+    /// Build an executable with the system toolchain
+    ///
+    /// The program has .text, .data and .bss to make several PT_LOAD segments and a BSS tail.
+    fn build_elf(name: &str, arch_flag: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "bitvisor_bootloader_elf_{}_{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+        let source = directory.join("image.c");
+        std::fs::write(
+            &source,
+            "int data = 1;\nint bss[1024];\nvoid _start(void) { bss[0] = data; for (;;); }\n",
+        )
+        .unwrap();
+        let output = directory.join("image.elf");
+        let status = Command::new("cc")
+            .args([arch_flag, "-nostdlib", "-static", "-O1", "-o"])
+            .arg(&output)
+            .arg(&source)
+            .status()
+            .expect("cc is not found");
+        assert!(status.success(), "cc {} failed", arch_flag);
+        output
+    }
+
+    fn readelf_segments(path: &PathBuf) -> Vec<ReadelfSegment> {
+        let output = Command::new("readelf")
+            .args(["-lW"])
+            .arg(path)
+            .output()
+            .expect("readelf is not found");
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 8 || !fields[1].starts_with("0x") {
+                    return None;
+                }
+                Some(ReadelfSegment {
+                    segment_type: fields[0].into(),
+                    offset: parse_hex(fields[1]),
+                    virtual_address: parse_hex(fields[2]),
+                    physical_address: parse_hex(fields[3]),
+                    file_size: parse_hex(fields[4]),
+                    memory_size: parse_hex(fields[5]),
+                    flags: fields[6..fields.len() - 1].concat(),
+                    alignment: parse_hex(fields[fields.len() - 1]),
+                })
+            })
+            .collect()
+    }
+
+    fn check_segments(name: &str, arch_flag: &str, class: ElfClass) {
+        let path = build_elf(name, arch_flag);
+        let data = std::fs::read(&path).unwrap();
+        let expected = readelf_segments(&path);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        let elf_file = ElfFile::new(&data).unwrap();
+        assert_eq!(elf_file.get_class(), class);
+        assert_eq!(elf_file.get_num_of_program_header_entries(), expected.len());
+        assert!(expected.iter().any(|e| e.segment_type == "LOAD"));
+        assert!(expected.iter().any(|e| e.segment_type != "LOAD"));
+        for (index, e) in expected.iter().enumerate() {
+            if e.segment_type != "LOAD" {
+                assert!(elf_file.get_segment_info(index).is_none(), "{}", index);
+                continue;
+            }
+            let segment = elf_file.get_segment_info(index).unwrap();
+            assert_eq!(segment.file_offset, e.offset);
+            assert_eq!(segment.virtual_base_address, e.virtual_address);
+            assert_eq!(segment.physical_base_address, e.physical_address);
+            assert_eq!(segment.file_size, e.file_size);
+            assert_eq!(segment.memory_size, e.memory_size);
+            assert_eq!(segment.alignment, e.alignment);
+            assert_eq!(segment.readable, e.flags.contains('R'));
+            assert_eq!(segment.writable, e.flags.contains('W'));
+            assert_eq!(segment.executable, e.flags.contains('E'));
         }
+        if let Some(index) = expected.iter().position(|e| e.segment_type == "NOTE") {
+            let note = elf_file.get_segment_info_by_type(index, PT_NOTE).unwrap();
+            assert_eq!(note.file_offset, expected[index].offset);
+        }
+
+        let num_of_entries = elf_file.get_num_of_program_header_entries();
+        assert!(elf_file.get_segment_info(num_of_entries).is_none());
+        assert!(elf_file.get_segment_info(usize::MAX).is_none());
+        assert!(elf_file
+            .get_segment_info_by_type(usize::MAX, PT_NOTE)
+            .is_none());
+    }
+
+    #[test]
+    fn elf32_segments_match_readelf() {
+        check_segments("elf32", "-m32", ElfClass::Elf32);
+    }
+
+    #[test]
+    fn elf64_segments_match_readelf() {
+        check_segments("elf64", "-m64", ElfClass::Elf64);
+    }
+
+    /// Get (value, size) of the symbol printed by `readelf -sW`
+    fn readelf_symbol(path: &PathBuf, name: &str) -> Option<(usize, usize)> {
+        let output = Command::new("readelf")
+            .args(["-sW"])
+            .arg(path)
+            .output()
+            .expect("readelf is not found");
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .find_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 8 || fields[7] != name {
+                    return None;
+                }
+                Some((parse_hex(fields[1]), fields[2].parse().unwrap()))
+            })
+    }
+
+    fn check_symbols(name: &str, arch_flag: &str) {
+        let path = build_elf(name, arch_flag);
+        let data = std::fs::read(&path).unwrap();
+        let expected_data = readelf_symbol(&path, "data").unwrap();
+        let expected_bss = readelf_symbol(&path, "bss").unwrap();
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        let elf_file = ElfFile::new(&data).unwrap();
+        let symbol = elf_file.find_symbol("data").unwrap();
+        assert_eq!(symbol.name, "data");
+        assert_eq!((symbol.value, symbol.size), expected_data);
+        assert_eq!(symbol.size, 4);
+        let symbol = elf_file.find_symbol("bss").unwrap();
+        assert_eq!(symbol.name, "bss");
+        assert_eq!((symbol.value, symbol.size), expected_bss);
+        assert_eq!(symbol.size, 4096);
+        assert!(elf_file.find_symbol("_start").is_some());
+        assert!(elf_file.find_symbol("missing_symbol").is_none());
+    }
+
+    #[test]
+    fn elf32_find_symbol_matches_readelf() {
+        check_symbols("symbols32", "-m32");
+    }
+
+    #[test]
+    fn elf64_find_symbol_matches_readelf() {
+        check_symbols("symbols64", "-m64");
+    }
+
+    #[test]
+    fn truncated_program_header_table_is_rejected() {
+        let path = build_elf("truncated", "-m64");
+        let data = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        let header_end = size_of::<Elf64Header>();
+        assert_eq!(
+            ElfFile::new(&data[..header_end]).err(),
+            Some(ElfError::InvalidProgramHeaderTable)
+        );
+        assert_eq!(ElfFile::new(&data[..8]).err(), Some(ElfError::TooSmall));
     }
 }
@@ -0,0 +1,207 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Framebuffer Console
+//!
+//! Draw the text on the framebuffer of EFI_GRAPHICS_OUTPUT_PROTOCOL with the embedded font.
+//! This is used when the text output does not work after the graphics mode is set.
+//!
+
+use crate::font::{get_glyph, FONT_HEIGHT, FONT_WIDTH};
+use crate::uefi::graphics_output::{
+    EfiGraphicsOutputModeInformation, EfiGraphicsOutputProtocol, EfiGraphicsPixelFormat,
+    EfiPixelBitmask,
+};
+use crate::uefi::output::{EFI_BLACK, EFI_LIGHTGRAY};
+use crate::uefi::EfiStatus;
+
+const TAB_WIDTH: usize = 8;
+
+/// The RGB of the EFI text colors from EFI_BLACK to EFI_WHITE
+const COLOR_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0xAA],
+    [0x00, 0xAA, 0x00],
+    [0x00, 0xAA, 0xAA],
+    [0xAA, 0x00, 0x00],
+    [0xAA, 0x00, 0xAA],
+    [0xAA, 0x55, 0x00],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+    [0x55, 0x55, 0xFF],
+    [0x55, 0xFF, 0x55],
+    [0x55, 0xFF, 0xFF],
+    [0xFF, 0x55, 0x55],
+    [0xFF, 0x55, 0xFF],
+    [0xFF, 0xFF, 0x55],
+    [0xFF, 0xFF, 0xFF],
+];
+
+pub struct FramebufferConsole {
+    base: usize,
+    /// Pixels per scan line
+    stride: usize,
+    pixel_format: EfiGraphicsPixelFormat,
+    pixel_bitmask: EfiPixelBitmask,
+    columns: usize,
+    rows: usize,
+    column: usize,
+    row: usize,
+    foreground: u32,
+    background: u32,
+}
+
+impl FramebufferConsole {
+    /// Make the console on the current mode of the display
+    ///
+    /// # Result
+    /// If the mode has a framebuffer of 32-bit pixels, Ok(FramebufferConsole),
+    /// otherwise Err(EfiStatus::EfiUnsupported)
+    pub fn new(graphics_output: &EfiGraphicsOutputProtocol) -> Result<Self, EfiStatus> {
+        let mode = graphics_output.get_mode();
+        let info: &EfiGraphicsOutputModeInformation = graphics_output.get_mode_info();
+        if mode.frame_buffer_base == 0
+            || !matches!(
+                info.pixel_format,
+                EfiGraphicsPixelFormat::PixelRedGreenBlueReserved8BitPerColor
+                    | EfiGraphicsPixelFormat::PixelBlueGreenRedReserved8BitPerColor
+                    | EfiGraphicsPixelFormat::PixelBitMask
+            )
+        {
+            return Err(EfiStatus::EfiUnsupported);
+        }
+        let mut console = Self {
+            base: mode.frame_buffer_base,
+            stride: info.pixels_per_scan_line as usize,
+            pixel_format: info.pixel_format,
+            pixel_bitmask: info.pixel_information,
+            columns: info.horizontal_resolution as usize / FONT_WIDTH,
+            rows: info.vertical_resolution as usize / FONT_HEIGHT,
+            column: 0,
+            row: 0,
+            foreground: 0,
+            background: 0,
+        };
+        if console.columns == 0 || console.rows == 0 {
+            return Err(EfiStatus::EfiUnsupported);
+        }
+        console.set_color(EFI_LIGHTGRAY, EFI_BLACK);
+        Ok(console)
+    }
+
+    /// Set the colors by the EFI text colors
+    ///
+    /// # Arguments
+    /// * `foreground` - EFI_BLACK ~ EFI_WHITE
+    /// * `background` - EFI_BLACK ~ EFI_WHITE
+    pub fn set_color(&mut self, foreground: usize, background: usize) {
+        self.foreground = self.to_pixel(COLOR_PALETTE[foreground & 0x0F]);
+        self.background = self.to_pixel(COLOR_PALETTE[background & 0x0F]);
+    }
+
+    fn to_pixel(&self, [red, green, blue]: [u8; 3]) -> u32 {
+        match self.pixel_format {
+            EfiGraphicsPixelFormat::PixelRedGreenBlueReserved8BitPerColor => {
+                u32::from_le_bytes([red, green, blue, 0])
+            }
+            EfiGraphicsPixelFormat::PixelBlueGreenRedReserved8BitPerColor => {
+                u32::from_le_bytes([blue, green, red, 0])
+            }
+            _ => {
+                let bitmask = &self.pixel_bitmask;
+                to_masked_color(red, bitmask.red_mask)
+                    | to_masked_color(green, bitmask.green_mask)
+                    | to_masked_color(blue, bitmask.blue_mask)
+            }
+        }
+    }
+
+    /// Fill the screen with the background color and move the cursor to the top
+    pub fn clear_screen(&mut self) {
+        for y in 0..(self.rows * FONT_HEIGHT) {
+            self.fill_line(y);
+        }
+        self.column = 0;
+        self.row = 0;
+    }
+
+    pub fn write_str(&mut self, string: &str) {
+        for c in string.chars() {
+            match c {
+                '\n' => self.new_line(),
+                '\r' => self.column = 0,
+                '\t' => {
+                    for _ in 0..(TAB_WIDTH - self.column % TAB_WIDTH) {
+                        self.put_char(' ');
+                    }
+                }
+                _ => self.put_char(c),
+            }
+        }
+    }
+
+    fn put_char(&mut self, c: char) {
+        if self.column >= self.columns {
+            self.new_line();
+        }
+        let glyph = get_glyph(c);
+        let x = self.column * FONT_WIDTH;
+        for (i, line) in glyph.iter().enumerate() {
+            let pixels = self.line_address(self.row * FONT_HEIGHT + i) as *mut u32;
+            for bit in 0..FONT_WIDTH {
+                let color = if (line & (0x80 >> bit)) != 0 {
+                    self.foreground
+                } else {
+                    self.background
+                };
+                unsafe { core::ptr::write_volatile(pixels.add(x + bit), color) };
+            }
+        }
+        self.column += 1;
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+        /* Scroll up one row */
+        let line_size = self.stride * core::mem::size_of::<u32>();
+        unsafe {
+            core::ptr::copy(
+                self.line_address(FONT_HEIGHT) as *const u8,
+                self.line_address(0) as *mut u8,
+                line_size * (self.rows - 1) * FONT_HEIGHT,
+            )
+        };
+        for y in ((self.rows - 1) * FONT_HEIGHT)..(self.rows * FONT_HEIGHT) {
+            self.fill_line(y);
+        }
+    }
+
+    fn fill_line(&self, y: usize) {
+        let pixels = self.line_address(y) as *mut u32;
+        for x in 0..(self.columns * FONT_WIDTH) {
+            unsafe { core::ptr::write_volatile(pixels.add(x), self.background) };
+        }
+    }
+
+    fn line_address(&self, y: usize) -> usize {
+        self.base + y * self.stride * core::mem::size_of::<u32>()
+    }
+}
+
+/// Put the 8-bit color value into the bits of `mask`
+fn to_masked_color(value: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let width = mask.count_ones().min(8);
+    (((value as u32) >> (8 - width)) << mask.trailing_zeros()) & mask
+}
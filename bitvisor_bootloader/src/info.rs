@@ -1,9 +1,5 @@
-use crate::uefi::{
-    boot_service::EfiBootServices, file::EfiFileProtocol, EfiHandle, EfiStatus, Guid,
-};
-use core::{num::NonZeroUsize, ptr::NonNull};
-
-#[allow(dead_code)]
+use crate::uefi::{file::EfiFileProtocol, Guid};
+use core::num::NonZeroUsize;
 
 pub const UEFI_BITVISOR_BOOT_UUID: Guid = Guid {
     d1: 0x4CF80319,
@@ -12,6 +8,7 @@ pub const UEFI_BITVISOR_BOOT_UUID: Guid = Guid {
     d4: [0x9A, 0x87, 0x60, 0xE5, 0x86, 0xE7, 0x9D, 0x0F],
 };
 
+#[allow(dead_code)]
 pub const UEFI_BITVISOR_PASS_AUTH_UUID: Guid = Guid {
     d1: 0xE0970CB4,
     d2: 0xDF2E,
@@ -19,6 +16,7 @@ pub const UEFI_BITVISOR_PASS_AUTH_UUID: Guid = Guid {
     d4: [0xB1, 0xA9, 0x63, 0x3C, 0xCD, 0xE3, 0xA2, 0xC6],
 };
 
+#[allow(dead_code)]
 pub const UEFI_BITVISOR_CPU_TYPE_UUID: Guid = Guid {
     d1: 0x0992D209,
     d2: 0x72B0,
@@ -33,6 +31,7 @@ pub const UEFI_BITVISOR_DISCONNECT_CONTROLLER_UUID: Guid = Guid {
     d4: [0x9D, 0x93, 0xC3, 0x54, 0x69, 0x33, 0x46, 0x84],
 };
 
+#[allow(dead_code)]
 pub const UEFI_BITVISOR_ACPI_TABLE_MOD_UUID: Guid = Guid {
     d1: 0x79FF5F54,
     d2: 0x5392,
@@ -47,6 +46,28 @@ pub const UEFI_BITVISOR_DEV_TREE_UUID: Guid = Guid {
     d4: [0xBF, 0xCC, 0x02, 0xDB, 0x91, 0xAE, 0xD8, 0x10],
 };
 
+pub const UEFI_BITVISOR_COMMAND_LINE_UUID: Guid = Guid {
+    d1: 0xA0E9E36A,
+    d2: 0xF383,
+    d3: 0x4D42,
+    d4: [0xBC, 0x18, 0x46, 0x6A, 0xCB, 0x36, 0xE3, 0xE3],
+};
+
+pub const UEFI_BITVISOR_BOOT_LOG_UUID: Guid = Guid {
+    d1: 0x9A9F3D3F,
+    d2: 0x3958,
+    d3: 0x4AD9,
+    d4: [0x86, 0xBC, 0x0A, 0xCA, 0x17, 0xEB, 0x57, 0x05],
+};
+
+pub const UEFI_BITVISOR_GRAPHICS_OUTPUT_UUID: Guid = Guid {
+    d1: 0x2EF536CB,
+    d2: 0x0D66,
+    d3: 0x4922,
+    d4: [0xB2, 0xDE, 0x47, 0xF4, 0x6A, 0xBF, 0xAE, 0x77],
+};
+
+#[allow(dead_code)]
 pub const EFI_BLOCK_IO_CRYPTO_PROTOCOL_GUID: Guid = Guid {
     d1: 0xa00490ba,
     d2: 0x3f1a,
@@ -54,11 +75,30 @@ pub const EFI_BLOCK_IO_CRYPTO_PROTOCOL_GUID: Guid = Guid {
     d4: [0xab, 0x90, 0x4f, 0xa9, 0x97, 0x26, 0xa1, 0xe8],
 };
 
+pub const BITVISOR_NOTE_NAME: &[u8] = b"BitVisor";
+pub const BITVISOR_NOTE_BOOT_PROTOCOL: u32 = 1;
+pub const BITVISOR_BOOT_PROTOCOL_VERSION: u32 = 1;
+
+/// The descriptor of the "BitVisor" note telling how to load the image
+///
+/// Zero in each field except `version` means the default of the bootloader.
+#[repr(C)]
+pub struct BitVisorBootProtocolNote {
+    pub version: u32,
+    pub reserved: u32,
+    pub load_alignment: u64,
+    pub max_physical_address: u64,
+    pub bootstrap_size: u64,
+}
+
 #[repr(C)]
 pub struct BitVisorBoot {
     pub bitvisor_boot_uuid: Guid,
     pub bitvisor_memory_address: usize,
     pub bitvisor_size: usize,
+    /// The file of the image to read the rest after `bitvisor_size`
+    ///
+    /// Null if the file is compressed, `bitvisor_size` covers the whole image then.
     pub bitvisor_protocol: *const EfiFileProtocol,
 }
 
@@ -80,3 +120,41 @@ pub struct DtbTable {
     pub bitvisor_dtb_uuid: Guid,
     pub dtb_table_address: Option<NonZeroUsize>,
 }
+
+/// The command line given to the bootloader by LoadOptions
+#[repr(C)]
+pub struct BitVisorCommandLine {
+    pub bitvisor_command_line_uuid: Guid,
+    /// UTF-8 string terminated by a null character
+    pub command_line: *const u8,
+    /// The length of `command_line` without the null character
+    pub command_line_size: usize,
+}
+
+/// The current mode of EFI_GRAPHICS_OUTPUT_PROTOCOL
+///
+/// `pixel_format` and `pixel_information` are the same as EFI_GRAPHICS_OUTPUT_MODE_INFORMATION.
+#[repr(C)]
+pub struct BitVisorGraphicsOutput {
+    pub bitvisor_graphics_output_uuid: Guid,
+    pub mode: u32,
+    pub horizontal_resolution: u32,
+    pub vertical_resolution: u32,
+    pub pixels_per_scan_line: u32,
+    pub pixel_format: u32,
+    /// Red, green, blue and reserved masks used when `pixel_format` is PixelBitMask
+    pub pixel_information: [u32; 4],
+    pub frame_buffer_base: usize,
+    pub frame_buffer_size: usize,
+}
+
+/// The console output of the bootloader
+///
+/// `boot_log` is valid until the entry point of BitVisor returns.
+#[repr(C)]
+pub struct BitVisorBootLog {
+    pub bitvisor_boot_log_uuid: Guid,
+    /// UTF-8 text, not terminated by a null character
+    pub boot_log: *const u8,
+    pub boot_log_size: usize,
+}
@@ -0,0 +1,524 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Hypervisor ELF Loader
+//!
+//! Load every PT_LOAD segment of the hypervisor image into the memory
+//!
+//! If the firmware supports EFI_MEMORY_ATTRIBUTE_PROTOCOL, the loaded segments are
+//! protected as W^X following their p_flags.
+//!
+
+use crate::elf::{ElfClass, ElfFile, SegmentInfo, R_NONE};
+use crate::info::{
+    BitVisorBootProtocolNote, BITVISOR_BOOT_PROTOCOL_VERSION, BITVISOR_NOTE_BOOT_PROTOCOL,
+    BITVISOR_NOTE_NAME,
+};
+use crate::uefi::boot_service::{
+    EfiBootServices, EfiMemoryAttribute, EfiMemoryType, PAGE_SHIFT, PAGE_SIZE,
+};
+use crate::uefi::memory_attribute::EfiMemoryAttributeProtocol;
+use crate::uefi::EfiStatus;
+
+const MAX_LOAD_SEGMENTS: usize = 16;
+
+/// Loading parameters from the BitVisor boot protocol note
+#[derive(Clone, Copy, Debug)]
+pub struct BootProtocol {
+    pub version: u32,
+    pub load_alignment: Option<usize>,
+    pub max_physical_address: Option<usize>,
+    pub bootstrap_size: Option<usize>,
+}
+
+#[derive(Clone, Copy)]
+struct LoadedSegment {
+    load_address: usize,
+    virtual_address: usize,
+    memory_size: usize,
+}
+
+pub struct LoadedImage {
+    /// The physical address to jump into
+    pub entry_point: usize,
+    /// The address where the lowest segment is loaded
+    pub image_address: usize,
+    /// The size from the start of the lowest segment to the end of the highest segment
+    pub image_size: usize,
+    /// The pages holding all segments
+    page_address: usize,
+    pages: usize,
+    segments: [LoadedSegment; MAX_LOAD_SEGMENTS],
+    num_of_segments: usize,
+    memory_attribute: Option<&'static EfiMemoryAttributeProtocol>,
+}
+
+impl LoadedImage {
+    const fn new() -> Self {
+        Self {
+            entry_point: 0,
+            image_address: 0,
+            image_size: 0,
+            page_address: 0,
+            pages: 0,
+            segments: [LoadedSegment {
+                load_address: 0,
+                virtual_address: 0,
+                memory_size: 0,
+            }; MAX_LOAD_SEGMENTS],
+            num_of_segments: 0,
+            memory_attribute: None,
+        }
+    }
+
+    fn add_segment(&mut self, segment: LoadedSegment) {
+        self.segments[self.num_of_segments] = segment;
+        self.num_of_segments += 1;
+    }
+
+    /// Convert the address in the loaded segments to the linked virtual address
+    ///
+    /// # Result
+    /// If `physical_address` is inside of a loaded segment, Some(virtual_address), otherwise None
+    pub fn physical_to_virtual(&self, physical_address: usize) -> Option<usize> {
+        self.segments[..self.num_of_segments]
+            .iter()
+            .find(|s| {
+                s.load_address <= physical_address
+                    && physical_address < s.load_address + s.memory_size
+            })
+            .map(|s| s.virtual_address + (physical_address - s.load_address))
+    }
+
+    /// Convert the linked virtual address to the address in the loaded segments
+    ///
+    /// # Result
+    /// If `size` bytes from `virtual_address` are inside of a loaded segment,
+    /// Some(physical_address), otherwise None
+    pub fn virtual_to_physical(&self, virtual_address: usize, size: usize) -> Option<usize> {
+        self.segments[..self.num_of_segments]
+            .iter()
+            .find(|s| {
+                s.virtual_address <= virtual_address
+                    && virtual_address
+                        .checked_add(size)
+                        .is_some_and(|end| end <= s.virtual_address + s.memory_size)
+            })
+            .map(|s| s.load_address + (virtual_address - s.virtual_address))
+    }
+
+    /// Apply W^X page attributes to the loaded segments following their p_flags
+    ///
+    /// Code becomes read-only and executable, writable data becomes non-executable and
+    /// read-only data becomes both. Pages shared with a segment having other permissions
+    /// and segments which are writable and executable are left as they are.
+    ///
+    /// # Arguments
+    /// * `elf_file` - the parsed hypervisor image loaded into this
+    /// * `memory_attribute` - EFI_MEMORY_ATTRIBUTE_PROTOCOL of the firmware
+    fn protect_segments(
+        &mut self,
+        elf_file: &ElfFile,
+        memory_attribute: &'static EfiMemoryAttributeProtocol,
+    ) {
+        self.memory_attribute = Some(memory_attribute);
+        let ro = EfiMemoryAttribute::EfiMemoryRo as u64;
+        let xp = EfiMemoryAttribute::EfiMemoryXp as u64;
+        let page_range = |segment: &SegmentInfo| -> Option<(usize, usize)> {
+            let address = self.virtual_to_physical(segment.virtual_base_address, 0)?;
+            Some((
+                address & !(PAGE_SIZE - 1),
+                (address + segment.memory_size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1),
+            ))
+        };
+
+        for index in 0..elf_file.get_num_of_program_header_entries() {
+            let Some(segment) = elf_file.get_segment_info(index) else {
+                continue;
+            };
+            if segment.writable && segment.executable {
+                warn!(
+                    "Segment {:#X} is writable and executable, leave it as is",
+                    segment.virtual_base_address
+                );
+                continue;
+            }
+            let Some((mut start, mut end)) = page_range(&segment) else {
+                continue;
+            };
+            for other_index in 0..elf_file.get_num_of_program_header_entries() {
+                let Some(other) = elf_file.get_segment_info(other_index) else {
+                    continue;
+                };
+                if other_index == index
+                    || (other.writable == segment.writable
+                        && other.executable == segment.executable)
+                {
+                    continue;
+                }
+                let Some((other_start, other_end)) = page_range(&other) else {
+                    continue;
+                };
+                if other_start <= start && start < other_end {
+                    start += PAGE_SIZE;
+                }
+                if other_start < end && end <= other_end {
+                    end -= PAGE_SIZE;
+                }
+            }
+            if start >= end {
+                continue;
+            }
+
+            let (set, clear) = if segment.executable {
+                (ro, xp)
+            } else if segment.writable {
+                (xp, ro)
+            } else {
+                (ro | xp, 0)
+            };
+            let result = memory_attribute
+                .set_attributes(start, end - start, set)
+                .and_then(|_| {
+                    if clear != 0 {
+                        memory_attribute.clear_attributes(start, end - start, clear)
+                    } else {
+                        Ok(())
+                    }
+                });
+            match result {
+                Ok(()) => debug!(
+                    "Protect {:#X} ~ {:#X} as {}{}{}",
+                    start,
+                    end,
+                    if segment.readable { 'R' } else { '-' },
+                    if segment.writable { 'W' } else { '-' },
+                    if segment.executable { 'X' } else { '-' }
+                ),
+                Err(e) => warn!(
+                    "Failed to set the attributes of {:#X} ~ {:#X}: {:?}",
+                    start, end, e
+                ),
+            }
+        }
+    }
+
+    /// Free the pages allocated for the segments
+    pub fn free(&self, b_s: &EfiBootServices) {
+        if self.pages == 0 {
+            return;
+        }
+        if let Some(memory_attribute) = self.memory_attribute {
+            /* Make the pages writable again before returning them to the firmware */
+            let _ = memory_attribute.clear_attributes(
+                self.page_address,
+                self.pages << PAGE_SHIFT,
+                EfiMemoryAttribute::EfiMemoryRo as u64 | EfiMemoryAttribute::EfiMemoryXp as u64,
+            );
+        }
+        if let Err(e) = b_s.free_memory(self.page_address, self.pages) {
+            warn!(
+                "Failed to free the image at {:#X}: {:?}",
+                self.page_address, e
+            );
+        }
+    }
+}
+
+/// Read the BitVisor boot protocol note of the image
+///
+/// # Result
+/// If the note exists, Ok(Some(BootProtocol)), if not, Ok(None).
+/// If the note is broken or its version is not supported, Err(EfiStatus)
+pub fn read_boot_protocol(elf_file: &ElfFile) -> Result<Option<BootProtocol>, EfiStatus> {
+    let Some(note) = elf_file.find_note(BITVISOR_NOTE_NAME, BITVISOR_NOTE_BOOT_PROTOCOL) else {
+        return Ok(None);
+    };
+    if note.descriptor.len() < core::mem::size_of::<BitVisorBootProtocolNote>() {
+        error!(
+            "The BitVisor boot protocol note is too small: {} bytes",
+            note.descriptor.len()
+        );
+        return Err(EfiStatus::EfiLoadError);
+    }
+    let descriptor = unsafe {
+        core::ptr::read_unaligned(note.descriptor.as_ptr() as *const BitVisorBootProtocolNote)
+    };
+    let non_zero = |x: u64| if x == 0 { None } else { Some(x as usize) };
+    let boot_protocol = BootProtocol {
+        version: descriptor.version,
+        load_alignment: non_zero(descriptor.load_alignment),
+        max_physical_address: non_zero(descriptor.max_physical_address),
+        bootstrap_size: non_zero(descriptor.bootstrap_size),
+    };
+    if boot_protocol.version != BITVISOR_BOOT_PROTOCOL_VERSION {
+        error!(
+            "Unsupported BitVisor boot protocol version {} (this bootloader supports {})",
+            boot_protocol.version, BITVISOR_BOOT_PROTOCOL_VERSION
+        );
+        return Err(EfiStatus::EfiIncompatibleVersion);
+    }
+    if let Some(load_alignment) = boot_protocol.load_alignment {
+        if !load_alignment.is_power_of_two() {
+            error!("Invalid load alignment: {:#X}", load_alignment);
+            return Err(EfiStatus::EfiLoadError);
+        }
+    }
+    Ok(Some(boot_protocol))
+}
+
+/// Load all PT_LOAD segments of the ELF image
+///
+/// All segments are placed in one allocation keeping the distances between them.
+/// A position independent image (ET_DYN) is placed at the highest memory under `upper_address`
+/// and its R_*_RELATIVE relocations are applied.
+/// Otherwise, the image is placed at the lowest `p_paddr` if the pages are available,
+/// or at the highest memory under `upper_address` aligned to the largest `p_align`.
+/// The pages are EfiLoaderCode if any segment is executable, otherwise EfiLoaderData.
+/// After loading, the segments are protected as W^X when the firmware supports
+/// EFI_MEMORY_ATTRIBUTE_PROTOCOL.
+///
+/// # Arguments
+/// * `b_s` - EfiBootService
+/// * `elf_file` - the parsed hypervisor image
+/// * `upper_address` - the upper border address for the allocation
+/// * `minimum_alignment` - the alignment used when `p_align` is smaller than this
+///
+/// # Result
+/// If all segments are loaded, Ok(LoadedImage), otherwise Err(EfiStatus)
+pub fn load_elf(
+    b_s: &EfiBootServices,
+    elf_file: &ElfFile,
+    upper_address: usize,
+    minimum_alignment: usize,
+) -> Result<LoadedImage, EfiStatus> {
+    let mut image = LoadedImage::new();
+    if let Err(e) = load_segments(b_s, elf_file, upper_address, minimum_alignment, &mut image) {
+        image.free(b_s);
+        return Err(e);
+    }
+
+    let entry_point = elf_file.get_entry_point();
+    let Some(entry_point) = image.virtual_to_physical(entry_point, 1) else {
+        error!("No segment contains the entry point {:#X}", entry_point);
+        image.free(b_s);
+        return Err(EfiStatus::EfiLoadError);
+    };
+    image.entry_point = entry_point;
+
+    match EfiMemoryAttributeProtocol::locate(b_s) {
+        Ok(memory_attribute) => image.protect_segments(elf_file, memory_attribute),
+        Err(_) => warn!("EFI_MEMORY_ATTRIBUTE_PROTOCOL is not found, segments stay RWX"),
+    }
+    Ok(image)
+}
+
+/// Place all PT_LOAD segments as one block keeping their layout
+///
+/// The segments are arranged by `p_vaddr` for a position independent image
+/// and by `p_paddr` for the others. An image which is not position independent
+/// is loaded at another address only if it has relocation entries.
+fn load_segments(
+    b_s: &EfiBootServices,
+    elf_file: &ElfFile,
+    upper_address: usize,
+    minimum_alignment: usize,
+    image: &mut LoadedImage,
+) -> Result<(), EfiStatus> {
+    let is_position_independent = elf_file.is_position_independent();
+    let base_address = |segment: &SegmentInfo| {
+        if is_position_independent {
+            segment.virtual_base_address
+        } else {
+            segment.physical_base_address
+        }
+    };
+
+    let mut lowest_address = usize::MAX;
+    let mut highest_address = 0;
+    let mut alignment = minimum_alignment.max(PAGE_SIZE);
+    let mut memory_type = EfiMemoryType::EfiLoaderData;
+    for index in 0..elf_file.get_num_of_program_header_entries() {
+        let Some(segment) = elf_file.get_segment_info(index) else {
+            continue;
+        };
+        lowest_address = lowest_address.min(base_address(&segment));
+        highest_address = highest_address.max(base_address(&segment) + segment.memory_size);
+        alignment = alignment.max(segment.alignment);
+        if segment.executable {
+            memory_type = EfiMemoryType::EfiLoaderCode;
+        }
+    }
+    if lowest_address > highest_address {
+        error!("No PT_LOAD segment");
+        return Err(EfiStatus::EfiLoadError);
+    }
+
+    let lowest_page = lowest_address & !(PAGE_SIZE - 1);
+    let pages = (highest_address - lowest_page + PAGE_SIZE - 1) >> PAGE_SHIFT;
+    let page_address = if is_position_independent {
+        b_s.alloc_highest_aligned_memory(pages, alignment, upper_address, memory_type)?
+    } else {
+        match b_s.alloc_memory_at(lowest_page, pages, memory_type) {
+            Ok(a) => a,
+            Err(e) if has_relocations(elf_file) => {
+                warn!(
+                    "{:#X} ~ {:#X} is not available ({:?}), load the image at another address",
+                    lowest_page, highest_address, e
+                );
+                b_s.alloc_highest_aligned_memory(pages, alignment, upper_address, memory_type)?
+            }
+            Err(e) => {
+                error!(
+                    "{:#X} ~ {:#X} is not available ({:?}) and the image is not relocatable",
+                    lowest_page, highest_address, e
+                );
+                return Err(e);
+            }
+        }
+    };
+    image.page_address = page_address;
+    image.pages = pages;
+    image.image_address = page_address + (lowest_address - lowest_page);
+    image.image_size = highest_address - lowest_address;
+    unsafe { core::ptr::write_bytes(page_address as *mut u8, 0, pages << PAGE_SHIFT) };
+
+    for index in 0..elf_file.get_num_of_program_header_entries() {
+        let Some(segment) = elf_file.get_segment_info(index) else {
+            continue;
+        };
+        if image.num_of_segments >= MAX_LOAD_SEGMENTS {
+            error!("Too many PT_LOAD segments");
+            return Err(EfiStatus::EfiLoadError);
+        }
+        let load_address = page_address + (base_address(&segment) - lowest_page);
+        image.add_segment(LoadedSegment {
+            load_address,
+            virtual_address: segment.virtual_base_address,
+            memory_size: segment.memory_size,
+        });
+        copy_segment(elf_file, &segment, load_address)?;
+    }
+    if is_position_independent || page_address != lowest_page {
+        apply_relocations(elf_file, image, page_address.wrapping_sub(lowest_page))?;
+    }
+    Ok(())
+}
+
+/// Check if the image has any relocation entry to move it from its link address
+fn has_relocations(elf_file: &ElfFile) -> bool {
+    elf_file
+        .relocations()
+        .is_ok_and(|mut r| r.any(|relocation| relocation.relocation_type != R_NONE))
+}
+
+fn copy_segment(
+    elf_file: &ElfFile,
+    segment: &SegmentInfo,
+    load_address: usize,
+) -> Result<(), EfiStatus> {
+    let Some(segment_data) = elf_file.get_segment_data(segment) else {
+        error!(
+            "Segment at {:#X} is out of the image",
+            segment.virtual_base_address
+        );
+        return Err(EfiStatus::EfiLoadError);
+    };
+    debug!(
+        "Load segment {:#X} at {:#X} ~ {:#X}",
+        segment.virtual_base_address,
+        load_address,
+        load_address + segment.memory_size
+    );
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            segment_data.as_ptr(),
+            load_address as *mut u8,
+            segment_data.len(),
+        );
+        /* Clear the BSS area */
+        core::ptr::write_bytes(
+            (load_address + segment.file_size) as *mut u8,
+            0,
+            segment.memory_size - segment.file_size,
+        )
+    };
+    Ok(())
+}
+
+/// Apply R_*_RELATIVE relocations
+///
+/// # Arguments
+/// * `elf_file` - the parsed hypervisor image
+/// * `image` - the loaded segments of `elf_file`
+/// * `load_bias` - the difference between the loaded address and the linked address
+fn apply_relocations(
+    elf_file: &ElfFile,
+    image: &LoadedImage,
+    load_bias: usize,
+) -> Result<(), EfiStatus> {
+    let relocations = match elf_file.relocations() {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Failed to read the relocation table: {:?}", e);
+            return Err(EfiStatus::EfiLoadError);
+        }
+    };
+    let relative_type = elf_file.get_relative_relocation_type();
+    let word_size = match elf_file.get_class() {
+        ElfClass::Elf32 => 4,
+        ElfClass::Elf64 => 8,
+    };
+    let mut num_of_relocations = 0usize;
+    for relocation in relocations {
+        if relocation.relocation_type == R_NONE {
+            continue;
+        }
+        if relocation.symbol_index != 0 {
+            error!(
+                "Relocation at {:#X} refers to the symbol {}, symbols are not resolved",
+                relocation.offset, relocation.symbol_index
+            );
+            return Err(EfiStatus::EfiLoadError);
+        }
+        if Some(relocation.relocation_type) != relative_type {
+            error!(
+                "Unsupported relocation type {} at {:#X}",
+                relocation.relocation_type, relocation.offset
+            );
+            return Err(EfiStatus::EfiLoadError);
+        }
+        let Some(address) = image.virtual_to_physical(relocation.offset, word_size) else {
+            error!("Relocation at {:#X} is out of the image", relocation.offset);
+            return Err(EfiStatus::EfiLoadError);
+        };
+        unsafe {
+            match elf_file.get_class() {
+                ElfClass::Elf32 => {
+                    let target = address as *mut u32;
+                    let value = match relocation.addend {
+                        Some(addend) => load_bias.wrapping_add(addend as usize) as u32,
+                        None => target.read_unaligned().wrapping_add(load_bias as u32),
+                    };
+                    target.write_unaligned(value);
+                }
+                ElfClass::Elf64 => {
+                    let target = address as *mut u64;
+                    let value = match relocation.addend {
+                        Some(addend) => load_bias.wrapping_add(addend as usize) as u64,
+                        None => target.read_unaligned().wrapping_add(load_bias as u64),
+                    };
+                    target.write_unaligned(value);
+                }
+            }
+        }
+        num_of_relocations += 1;
+    }
+    debug!("Applied {} relocations", num_of_relocations);
+    Ok(())
+}
@@ -0,0 +1,264 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Console Logger
+//!
+//! Write the log records to the console with the time since the loader started.
+//! The level is chosen per module by a filter like `info,loader=debug,uefi::file=trace`.
+//!
+//! The time is measured by the TSC calibrated with Stall() instead of GetNextMonotonicCount().
+//! The monotonic count is only a sequence number and has no relation to the time,
+//! and it is a boot service which must not be called after ExitBootServices().
+//! The TSC is used only if it is invariant, otherwise its rate changes with the power state
+//! and the records have no time. The loader runs only on the bootstrap processor,
+//! so the TSC values are not compared across the cores.
+//!
+
+use crate::console;
+use crate::cpu::{has_invariant_tsc, read_tsc};
+use crate::uefi::boot_service::EfiBootServices;
+use crate::uefi::output::{EFI_LIGHTRED, EFI_YELLOW};
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+pub const MAX_MODULE_FILTERS: usize = 8;
+const MAX_MODULE_NAME_LENGTH: usize = 32;
+pub const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// The time to measure the TSC frequency
+const CALIBRATION_MICROSECONDS: usize = 1000;
+
+#[derive(Clone, Copy)]
+struct ModuleFilter {
+    name: [u8; MAX_MODULE_NAME_LENGTH],
+    length: usize,
+    level: LevelFilter,
+}
+
+impl ModuleFilter {
+    const fn new() -> Self {
+        Self {
+            name: [0; MAX_MODULE_NAME_LENGTH],
+            length: 0,
+            level: LevelFilter::Off,
+        }
+    }
+
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.length]).unwrap_or("")
+    }
+
+    /// Check if `module` is the module or its child, `module` has no crate name
+    fn matches(&self, module: &str) -> bool {
+        let name = self.name();
+        module == name || (module.starts_with(name) && module[name.len()..].starts_with("::"))
+    }
+}
+
+/// The log levels of the modules
+#[derive(Clone, Copy)]
+pub struct LogFilter {
+    default_level: LevelFilter,
+    modules: [ModuleFilter; MAX_MODULE_FILTERS],
+    num_of_modules: usize,
+}
+
+impl LogFilter {
+    pub const fn new(default_level: LevelFilter) -> Self {
+        Self {
+            default_level,
+            modules: [ModuleFilter::new(); MAX_MODULE_FILTERS],
+            num_of_modules: 0,
+        }
+    }
+
+    /// Parse the filter
+    ///
+    /// The filter is comma separated `level` and `module=level`, like `warn,loader=debug`.
+    /// `module` is the path without the crate name, and it includes the child modules.
+    ///
+    /// # Result
+    /// If the filter is valid, Some(LogFilter), otherwise None
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut filter = Self::new(DEFAULT_LOG_LEVEL);
+        for directive in spec.split(',').map(|d| d.trim()).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                None => filter.default_level = directive.parse().ok()?,
+                Some((module, level)) => {
+                    let module = module.trim();
+                    if filter.num_of_modules >= MAX_MODULE_FILTERS
+                        || module.is_empty()
+                        || module.len() > MAX_MODULE_NAME_LENGTH
+                    {
+                        return None;
+                    }
+                    let entry = &mut filter.modules[filter.num_of_modules];
+                    entry.name[..module.len()].copy_from_slice(module.as_bytes());
+                    entry.length = module.len();
+                    entry.level = level.trim().parse().ok()?;
+                    filter.num_of_modules += 1;
+                }
+            }
+        }
+        Some(filter)
+    }
+
+    /// Make the default level at least as verbose as `level`
+    pub fn raise_default_level(&mut self, level: LevelFilter) {
+        self.default_level = self.default_level.max(level);
+    }
+
+    /// Get the level of the module, the longest matching filter is used
+    ///
+    /// # Arguments
+    /// * `module` - the module path without the crate name
+    pub fn get_level(&self, module: &str) -> LevelFilter {
+        self.modules[..self.num_of_modules]
+            .iter()
+            .filter(|m| m.matches(module))
+            .max_by_key(|m| m.length)
+            .map(|m| m.level)
+            .unwrap_or(self.default_level)
+    }
+
+    /// Get the most verbose level of all modules
+    pub fn get_max_level(&self) -> LevelFilter {
+        self.modules[..self.num_of_modules]
+            .iter()
+            .map(|m| m.level)
+            .fold(self.default_level, Ord::max)
+    }
+}
+
+impl fmt::Debug for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}", self.default_level)?;
+        for m in &self.modules[..self.num_of_modules] {
+            write!(f, ",{}={}", m.name(), m.level)?;
+        }
+        write!(f, "\"")
+    }
+}
+
+/// LogFilter which can be replaced while the logger is in use
+struct SharedFilter {
+    lock: AtomicBool,
+    filter: UnsafeCell<LogFilter>,
+}
+
+unsafe impl Sync for SharedFilter {}
+
+impl SharedFilter {
+    const fn new(filter: LogFilter) -> Self {
+        Self {
+            lock: AtomicBool::new(false),
+            filter: UnsafeCell::new(filter),
+        }
+    }
+
+    /// Call `f` with the filter while holding the lock
+    fn with<R>(&self, f: impl FnOnce(&mut LogFilter) -> R) -> R {
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let result = f(unsafe { &mut *self.filter.get() });
+        self.lock.store(false, Ordering::Release);
+        result
+    }
+}
+
+pub struct ConsoleLogger {
+    filter: SharedFilter,
+    start_tsc: AtomicU64,
+    /// TSC ticks per second, 0 if unknown
+    tsc_frequency: AtomicU64,
+}
+
+static LOGGER: ConsoleLogger = ConsoleLogger {
+    filter: SharedFilter::new(LogFilter::new(DEFAULT_LOG_LEVEL)),
+    start_tsc: AtomicU64::new(0),
+    tsc_frequency: AtomicU64::new(0),
+};
+
+/// Remove the crate name from the module path, the crate root is shown as `main`
+fn strip_crate_name(target: &str) -> &str {
+    let crate_name = module_path!().split("::").next().unwrap_or("");
+    if target == crate_name {
+        return "main";
+    }
+    target
+        .strip_prefix(crate_name)
+        .and_then(|t| t.strip_prefix("::"))
+        .unwrap_or(target)
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let module = strip_crate_name(metadata.target());
+        metadata.level() <= self.filter.with(|filter| filter.get_level(module))
+    }
+
+    fn log(&self, record: &Record) {
+        let args = format_args!(
+            "{:<5} {}: {}\n",
+            record.level(),
+            strip_crate_name(record.target()),
+            record.args()
+        );
+        let args = match self.tsc_frequency.load(Ordering::Relaxed) {
+            0 => format_args!("[   -.------] {}", args),
+            frequency => {
+                let elapsed = read_tsc().wrapping_sub(self.start_tsc.load(Ordering::Relaxed));
+                format_args!(
+                    "[{:4}.{:06}] {}",
+                    elapsed / frequency,
+                    (elapsed % frequency) * 1_000_000 / frequency,
+                    args
+                )
+            }
+        };
+        match record.level() {
+            Level::Error => console::print_with_color(EFI_LIGHTRED, args),
+            Level::Warn => console::print_with_color(EFI_YELLOW, args),
+            _ => console::print(args),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Start the logger with the default filter
+///
+/// # Arguments
+/// * `b_s` - EfiBootService to measure the TSC frequency
+pub fn init(b_s: &EfiBootServices) {
+    let start_tsc = read_tsc();
+    LOGGER.start_tsc.store(start_tsc, Ordering::Relaxed);
+    if has_invariant_tsc() && b_s.stall(CALIBRATION_MICROSECONDS).is_ok() {
+        LOGGER.tsc_frequency.store(
+            read_tsc().wrapping_sub(start_tsc) * (1_000_000 / CALIBRATION_MICROSECONDS as u64),
+            Ordering::Relaxed,
+        );
+    }
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LOGGER.filter.with(|filter| filter.get_max_level()));
+    }
+}
+
+/// Change the levels of the modules
+pub fn set_filter(filter: LogFilter) {
+    LOGGER.filter.with(|f| *f = filter);
+    log::set_max_level(filter.get_max_level());
+}
@@ -1,7 +1,5 @@
-#![no_std]
-#![no_main]
-#![feature(c_variadic)]
-///https://doc.rust-lang.org/beta/unstable-book/language-features/c-variadic.html
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
 // Copyright (c) 2022 RIKEN
 // Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
@@ -22,26 +20,50 @@
 pub mod uefi;
 #[macro_use]
 pub mod console;
+#[macro_use]
+extern crate log;
+mod acpi;
+mod bootlog;
+#[cfg(any(feature = "bsdriver-embed", feature = "bsdriver-file"))]
 mod bsdriver;
+#[cfg(all(feature = "bsdriver-embed", feature = "bsdriver-file"))]
+compile_error!("bsdriver-embed and bsdriver-file cannot be enabled together");
+mod cmdline;
+mod config;
 mod cpu;
+mod decompress;
 mod elf;
+mod font;
+mod framebuffer;
 mod info;
+mod loader;
+mod logger;
+mod menu;
+mod serial;
 
-use bsdriver::load_bsdriver;
+use cmdline::CommandLine;
+use config::BootConfig;
+use console::ConsoleOutput;
 use core::{
-    mem::MaybeUninit,
     num::NonZeroUsize,
-    ptr::{null, null_mut},
-    result,
+    ptr::{addr_of, addr_of_mut},
 };
-use cpu::halt_loop;
+use framebuffer::FramebufferConsole;
 use info::{
-    AcpiTable, BitVisorBoot, BitVisorDisconnectController, DtbTable, UEFI_BITVISOR_BOOT_UUID,
-    UEFI_BITVISOR_DEV_TREE_UUID, UEFI_BITVISOR_DISCONNECT_CONTROLLER_UUID,
+    AcpiTable, BitVisorBoot, BitVisorBootLog, BitVisorCommandLine, BitVisorDisconnectController,
+    BitVisorGraphicsOutput, DtbTable, UEFI_BITVISOR_BOOT_LOG_UUID, UEFI_BITVISOR_BOOT_UUID,
+    UEFI_BITVISOR_COMMAND_LINE_UUID, UEFI_BITVISOR_DEV_TREE_UUID,
+    UEFI_BITVISOR_DISCONNECT_CONTROLLER_UUID, UEFI_BITVISOR_GRAPHICS_OUTPUT_UUID,
 };
+use log::LevelFilter;
+use logger::LogFilter;
+use menu::{BootMenu, Utf16Str, MAX_PATH_LENGTH};
+use serial::{SerialPort, DEFAULT_UART_PORT};
 use uefi::{
     boot_service::{self, EfiBootServices},
     file::{self, EfiFileProtocol},
+    graphics_output::EfiGraphicsOutputProtocol,
+    loaded_image::EfiLoadedImageProtocol,
     EfiConfigurationTable, EfiHandle, EfiStatus, EfiSystemTable, EFI_ACPI_20_TABLE_GUID,
     EFI_DTB_TABLE_GUID,
 };
@@ -49,103 +71,284 @@ use uefi::{
 type KernelEntryPointFn = unsafe extern "efiapi" fn(EfiHandle, *mut EfiSystemTable, usize) -> i32;
 
 static UPPER_LOAD_ADDR: usize = 0x4000_0000;
-static ENTRY_BOOTSTRAP_CODE_SIZE: usize = 0x10000;
-static ENTRY_MASK: usize = 0xFFFF;
+
+const BOOT_DIRECTORY: &str = "EFI\\BOOT";
+const CONFIG_NAME: &str = "bitvisor.conf";
+const BOOT_LOG_NAME: &str = "bootlog.txt";
+const DEFAULT_IMAGE_NAME: &str = "bitvisor.elf";
+const IMAGE_NAME_PREFIX: &str = "bitvisor";
+const IMAGE_NAME_SUFFIXES: [&str; 2] = [".elf", ".elf.gz"];
+const DEFAULT_MENU_TIMEOUT: usize = 5;
+const MAX_SYSTEM_INFO_ENTRIES: usize = 7;
 
 static mut SYSTEM_TABLE_REF: *const EfiSystemTable = core::ptr::null();
 static mut IMAGE_HANDLE_REF: EfiHandle = 0;
 static mut BOOT_SERVICES: *mut EfiBootServices = core::ptr::null_mut();
 static mut BITVISOR_PROTOCOL_REF: *const EfiFileProtocol = core::ptr::null_mut();
+static mut LOAD_BSDRIVER: bool = true;
+static mut ACPI_TABLE_MOD_ENABLED: bool = true;
+static mut SAVE_BOOT_LOG: bool = true;
 
 #[no_mangle]
 extern "C" fn efi_main(image_handle: EfiHandle, system_table: *mut EfiSystemTable) -> EfiStatus {
     unsafe {
-        unsafe { SYSTEM_TABLE_REF = system_table };
-        unsafe { IMAGE_HANDLE_REF = image_handle };
-        console::DEFAULT_CONSOLE.init((*system_table).console_output_protocol);
+        SYSTEM_TABLE_REF = system_table;
+        IMAGE_HANDLE_REF = image_handle;
+        (*addr_of_mut!(console::DEFAULT_CONSOLE)).init((*system_table).console_output_protocol);
+        (*addr_of_mut!(console::DEFAULT_CONSOLE))
+            .init_input((*system_table).console_input_protocol);
     }
     let b_s = unsafe { &mut *((*system_table).efi_boot_services) };
     unsafe {
         BOOT_SERVICES = b_s as *mut EfiBootServices;
     }
+    logger::init(b_s);
+    match unsafe { (*addr_of!(console::DEFAULT_CONSOLE)).set_largest_mode() } {
+        Ok((columns, rows)) => debug!("Text mode: {}x{}", columns, rows),
+        Err(e) => warn!("Failed to set the text mode: {:?}", e),
+    }
+    /* The framebuffer is ready after the mode is set, because the mode may change it */
+    let graphics_output = EfiGraphicsOutputProtocol::locate(b_s).ok();
+    if let Some(graphics_output) = graphics_output {
+        match FramebufferConsole::new(graphics_output) {
+            Ok(framebuffer) => unsafe {
+                (*addr_of_mut!(console::DEFAULT_CONSOLE)).init_framebuffer(framebuffer)
+            },
+            Err(e) => debug!("The framebuffer console is not available: {:?}", e),
+        }
+    }
 
-    const PATH: &str = "EFI\\BOOT\\bitvisor.elf";
+    /* Parse LoadOptions given by Boot#### or the UEFI Shell */
+    let command_line = match EfiLoadedImageProtocol::open(image_handle, b_s) {
+        Ok(l) => CommandLine::from_load_options(l.get_load_options()),
+        Err(e) => {
+            warn!("Failed to get LoadOptions: {:?}", e);
+            CommandLine::new()
+        }
+    };
+    if !command_line.is_empty() {
+        println!("Command line: {}", command_line.as_str());
+    }
+    if command_line.has_switch("noacpimod") {
+        unsafe { ACPI_TABLE_MOD_ENABLED = false };
+    }
 
     let root_protocol =
         file::EfiFileProtocol::open_root_dir(image_handle, b_s).expect("Failed to open root file.");
-    let mut bitvisor_path_utf16: [u16; PATH.len() + 1] = [0; PATH.len() + 1];
-    for (i, m) in PATH.encode_utf16().enumerate() {
-        bitvisor_path_utf16[i] = m;
+    let config = read_boot_config(root_protocol, b_s);
+
+    /* Select the console, "console=" of the command line overrides the configuration */
+    let console_output = command_line
+        .get_value("console")
+        .and_then(|name| {
+            let console_output = ConsoleOutput::from_name(name);
+            if console_output.is_none() {
+                warn!("Unknown console: {}", name);
+            }
+            console_output
+        })
+        .or(config.console)
+        .unwrap_or(ConsoleOutput::Text);
+    if console_output.uses_serial() {
+        match SerialPort::open(b_s, config.serial_port.unwrap_or(DEFAULT_UART_PORT)) {
+            Ok(serial_port) => unsafe {
+                (*addr_of_mut!(console::DEFAULT_CONSOLE)).init_serial(serial_port)
+            },
+            Err(e) => warn!("Failed to open the serial port: {:?}", e),
+        }
+    }
+    unsafe { (*addr_of_mut!(console::DEFAULT_CONSOLE)).set_output(console_output) };
+    if config.framebuffer_console == Some(true)
+        && !unsafe { (*addr_of_mut!(console::DEFAULT_CONSOLE)).activate_framebuffer() }
+    {
+        warn!("The framebuffer console is not available");
     }
-    let bitvisor_protocol = file::EfiFileProtocol::open_file(root_protocol, &bitvisor_path_utf16)
-        .expect("Failed to open bitvisor file");
 
-    let mut bitvisor_protocol_ref: *const EfiFileProtocol = bitvisor_protocol;
-    unsafe { BITVISOR_PROTOCOL_REF = bitvisor_protocol_ref };
+    /* Select the log levels, "log=" of the command line overrides the configuration */
+    let mut log_filter = command_line
+        .get_value("log")
+        .and_then(|spec| {
+            let log_filter = LogFilter::parse(spec);
+            if log_filter.is_none() {
+                warn!("Invalid log filter: {}", spec);
+            }
+            log_filter
+        })
+        .or(config.log_filter)
+        .unwrap_or(LogFilter::new(logger::DEFAULT_LOG_LEVEL));
+    if command_line.has_switch("debug") {
+        log_filter.raise_default_level(LevelFilter::Debug);
+    }
+    logger::set_filter(log_filter);
 
-    /* Read ElfHeader */
-    let mut elf_header: MaybeUninit<elf::Elf32Header> = MaybeUninit::uninit();
-    const ELF32_HEADER_SIZE: usize = core::mem::size_of::<elf::Elf32Header>();
-    let read_size = bitvisor_protocol
-        .read(elf_header.as_mut_ptr() as *mut usize, ELF32_HEADER_SIZE)
-        .expect("Failed to read Elf header");
-    if read_size != core::mem::size_of_val(&elf_header) {
-        panic!(
-            "Expected {} bytes, but read {} bytes",
-            ELF32_HEADER_SIZE, read_size
+    /* Give a chance to boot without BitVisor or to enable the debug output */
+    let prompt_timeout = config.prompt_timeout.unwrap_or(0);
+    if prompt_timeout > 0 {
+        unsafe { (*addr_of!(console::DEFAULT_CONSOLE)).flush_input() };
+        println!(
+            "Press D for the debug output, or any other key to skip BitVisor ({}s)",
+            prompt_timeout
+        );
+        match console::wait_for_key(b_s, Some(prompt_timeout as u64 * 1000)) {
+            Some(key) if matches!(key.get_char(), Some('d' | 'D')) => {
+                log_filter.raise_default_level(LevelFilter::Debug);
+                logger::set_filter(log_filter);
+            }
+            Some(_) => {
+                println!("Skip BitVisor");
+                let _ = file::EfiFileProtocol::close_file(root_protocol);
+                return EfiStatus::EfiAborted;
+            }
+            None => {}
+        }
+    }
+    debug!("Boot configuration: {:?}", config);
+    if let Some(load_bsdriver) = config.load_bsdriver {
+        unsafe { LOAD_BSDRIVER = load_bsdriver };
+    }
+    #[cfg(feature = "bsdriver-file")]
+    if unsafe { LOAD_BSDRIVER && ACPI_TABLE_MOD_ENABLED } {
+        read_bsdriver(root_protocol, b_s, &config);
+    }
+    if let Some(save_boot_log) = config.save_boot_log {
+        unsafe { SAVE_BOOT_LOG = save_boot_log };
+    }
+
+    /* Collect the hypervisor images and choose one */
+    let mut menu = BootMenu::new();
+    let path_option = command_line.get_value("path").filter(|image_path| {
+        let is_added = menu.add_entry(image_path.encode_utf16(), true);
+        if !is_added {
+            warn!("Ignore path= because the path is too long: {}", image_path);
+        }
+        is_added
+    });
+    if path_option.is_some() {
+        /* The image given by the load option is the only entry */
+    } else if config.image_path().is_some() || config.menu_images().next().is_some() {
+        for (image_path, is_default) in config
+            .image_path()
+            .map(|p| (p, true))
+            .into_iter()
+            .chain(config.menu_images().map(|p| (p, false)))
+        {
+            if !menu.add_entry(image_path.encode_utf16(), is_default) {
+                warn!("Skip the image: {}", image_path);
+            }
+        }
+    } else {
+        scan_hypervisor_images(root_protocol, b_s, &mut menu);
+    }
+    if menu.is_empty() {
+        warn!(
+            "No hypervisor image is found in {}, try {}",
+            BOOT_DIRECTORY, DEFAULT_IMAGE_NAME
         );
+        menu.add_entry(boot_file_path(DEFAULT_IMAGE_NAME.encode_utf16()), true);
     }
-    println!("read {:#X}", read_size);
+    let selected = menu.select(b_s, config.menu_timeout.unwrap_or(DEFAULT_MENU_TIMEOUT));
+    let bitvisor_path_utf16 = menu.get_path(selected);
+    println!("Boot {}", Utf16Str(bitvisor_path_utf16));
+    let bitvisor_protocol = file::EfiFileProtocol::open_file(root_protocol, bitvisor_path_utf16)
+        .expect("Failed to open bitvisor file");
+
+    /* Read the whole image */
+    let mut image_buffer = bitvisor_protocol
+        .read_to_end(b_s)
+        .expect("Failed to read hypervisor");
 
-    bitvisor_protocol
-        .seek(0x0000)
-        .expect("Failed to seek for the program header");
-    let elf_header = unsafe { elf_header.assume_init() };
-    if !elf_header.check_elf_header() {
-        panic!("Failed to load the bitvisor");
+    /* Expand the image if it is compressed */
+    let compression = decompress::detect_compression(image_buffer.as_slice());
+    if let Some(format) = compression {
+        let decompressed_size = decompress::get_decompressed_size(format, image_buffer.as_slice())
+            .expect("Failed to get the decompressed size");
+        println!(
+            "Decompress {:?} image: {} bytes -> {} bytes",
+            format,
+            image_buffer.as_slice().len(),
+            decompressed_size
+        );
+        let mut decompressed_buffer =
+            file::FileBuffer::allocate(b_s, decompressed_size).expect("Failed to allocate memory");
+        if let Err(e) = decompress::decompress(
+            format,
+            image_buffer.as_slice(),
+            decompressed_buffer.as_mut_slice(),
+        ) {
+            panic!("Failed to decompress the bitvisor: {:?}", e);
+        }
+        image_buffer.free(b_s);
+        image_buffer = decompressed_buffer;
     }
+    /*
+     * BitVisor reads the part after the bootstrap from the file, which is useless when the file
+     * is compressed. Pass no file then, and the whole image is passed in the memory instead.
+     */
+    let bitvisor_protocol_ref: *const EfiFileProtocol = match compression {
+        Some(_) => core::ptr::null(),
+        None => bitvisor_protocol,
+    };
+    unsafe { BITVISOR_PROTOCOL_REF = bitvisor_protocol_ref };
+    let image = image_buffer.as_slice();
 
-    // ENTRY_BOOTSTRAP_CODE_SIZE までにある第2段階ブートローダーを読み込む
-    let program_header_entries_size = ENTRY_BOOTSTRAP_CODE_SIZE;
+    let elf_file = match elf::ElfFile::new(image) {
+        Ok(e) => e,
+        Err(e) => panic!("Failed to load the bitvisor: {:?}", e),
+    };
+    let boot_protocol = match loader::read_boot_protocol(&elf_file) {
+        Ok(p) => p,
+        Err(e) => {
+            error!("Refuse to boot the bitvisor");
+            image_buffer.free(b_s);
+            let _ = file::EfiFileProtocol::close_file(bitvisor_protocol);
+            let _ = file::EfiFileProtocol::close_file(root_protocol);
+            return e;
+        }
+    };
+    debug!("BitVisor boot protocol: {:?}", boot_protocol);
+    let upper_load_address = config
+        .max_load_address
+        .or(boot_protocol.and_then(|p| p.max_physical_address))
+        .unwrap_or(UPPER_LOAD_ADDR);
+    let load_alignment = boot_protocol
+        .and_then(|p| p.load_alignment)
+        .unwrap_or(boot_service::PAGE_SIZE);
 
-    let physical_address = b_s
-        .alloc_highest_memory(ENTRY_BOOTSTRAP_CODE_SIZE / 4096, UPPER_LOAD_ADDR)
-        .expect("Failed to allocate memory");
+    let loaded_image = loader::load_elf(b_s, &elf_file, upper_load_address, load_alignment)
+        .expect("Failed to load hypervisor");
     println!(
-        "Allocate memory at {:#X} ~ {:#X}",
-        physical_address,
-        physical_address + program_header_entries_size * 4096
+        "Load hypervisor at {:#X} ~ {:#X}",
+        loaded_image.image_address,
+        loaded_image.image_address + loaded_image.image_size
     );
-    /*println!(
-        "program_header_offset is {:#X}",
-        elf_header.get_program_header_offset()
-    );*/
-    //0x34
-    let mut position: u64 = 0;
-    (bitvisor_protocol.get_position)(bitvisor_protocol, &mut position);
-    println!("{:#X}", position);
-    let read_size = bitvisor_protocol
-        .read(physical_address as *mut usize, program_header_entries_size)
-        .expect("Failed to read hypervisor");
-    if read_size != program_header_entries_size {
+    let bootstrap_size = config
+        .bootstrap_size
+        .or(boot_protocol.and_then(|p| p.bootstrap_size))
+        .unwrap_or(loaded_image.image_size);
+    if bootstrap_size > loaded_image.image_size {
         panic!(
-            "Expected {} bytes, but read {} bytes",
-            program_header_entries_size, read_size
+            "The bootstrap size {:#X} exceeds the image size {:#X}",
+            bootstrap_size, loaded_image.image_size
         );
     }
-
-    let entry_point = elf_header.get_entry_point();
-
-    println!("Load hypervisor at {:#X}", entry_point);
+    let bootstrap_size = if compression.is_some() && bootstrap_size < loaded_image.image_size {
+        warn!(
+            "The compressed image cannot be read from the file, pass the whole {:#X} bytes",
+            loaded_image.image_size
+        );
+        loaded_image.image_size
+    } else {
+        bootstrap_size
+    };
     let boot_info = BitVisorBoot {
         bitvisor_boot_uuid: UEFI_BITVISOR_BOOT_UUID,
-        bitvisor_memory_address: physical_address,
-        bitvisor_size: ENTRY_BOOTSTRAP_CODE_SIZE,
+        bitvisor_memory_address: loaded_image.image_address,
+        bitvisor_size: bootstrap_size,
         bitvisor_protocol: unsafe { BITVISOR_PROTOCOL_REF },
     };
     let bitvisor_disconnect_info = BitVisorDisconnectController {
         bitvisor_disconnect_controller_uuid: UEFI_BITVISOR_DISCONNECT_CONTROLLER_UUID,
-        disconnect_controller: (b_s.disconnect_controller) as *const usize,
+        disconnect_controller: b_s.get_disconnect_controller_address(),
     };
     let acpi_table = AcpiTable {
         bitvisor_acpi_uuid: EFI_ACPI_20_TABLE_GUID,
@@ -157,23 +360,82 @@ extern "C" fn efi_main(image_handle: EfiHandle, system_table: *mut EfiSystemTabl
         dtb_table_address: dtb_address,
     };
 
-    let mut system_info_pointers: [*const usize; 5] = [core::ptr::null(); 5];
-    system_info_pointers[0] = &boot_info as *const BitVisorBoot as *const usize;
-    system_info_pointers[1] =
-        &bitvisor_disconnect_info as *const BitVisorDisconnectController as *const usize;
-    system_info_pointers[2] = &acpi_table as *const AcpiTable as *const usize;
-    system_info_pointers[3] = match dtb_address {
-        Some(_) => &dtb_table as *const DtbTable as *const usize,
-        None => core::ptr::null(),
+    let entry = loaded_image.entry_point;
+    println!("bitvisor entry point:{:#X}", entry);
+    if let Some((symbol, offset)) = loaded_image
+        .physical_to_virtual(entry)
+        .and_then(|a| elf_file.find_symbol_by_address(a))
+    {
+        println!("bitvisor entry symbol: {}+{:#X}", symbol.name, offset);
+    }
+
+    /* Keep the log on the ESP in case BitVisor does not come back */
+    save_boot_log(root_protocol);
+    let boot_log_info = match bootlog::copy_to_pool(b_s) {
+        Ok((boot_log_address, boot_log_size)) => Some(BitVisorBootLog {
+            bitvisor_boot_log_uuid: UEFI_BITVISOR_BOOT_LOG_UUID,
+            boot_log: boot_log_address as *const u8,
+            boot_log_size,
+        }),
+        Err(e) => {
+            warn!("Failed to copy the boot log: {:?}", e);
+            None
+        }
     };
-    system_info_pointers[4] = core::ptr::null();
 
-    let system_info_ptr =
-        &system_info_pointers as *const [*const usize; 5] as *const usize as usize;
+    let graphics_output_info = graphics_output.map(|g| {
+        let mode = g.get_mode();
+        let info = g.get_mode_info();
+        let bitmask = &info.pixel_information;
+        BitVisorGraphicsOutput {
+            bitvisor_graphics_output_uuid: UEFI_BITVISOR_GRAPHICS_OUTPUT_UUID,
+            mode: mode.mode,
+            horizontal_resolution: info.horizontal_resolution,
+            vertical_resolution: info.vertical_resolution,
+            pixels_per_scan_line: info.pixels_per_scan_line,
+            pixel_format: info.pixel_format as u32,
+            pixel_information: [
+                bitmask.red_mask,
+                bitmask.green_mask,
+                bitmask.blue_mask,
+                bitmask.reserved_mask,
+            ],
+            frame_buffer_base: mode.frame_buffer_base,
+            frame_buffer_size: mode.frame_buffer_size,
+        }
+    });
 
-    let entry = (entry_point & ENTRY_MASK) + physical_address;
-    println!("programmer_header_pool:{:#X}", physical_address);
-    println!("bitvisor entry point:{:#X}", entry);
+    let command_line_info = BitVisorCommandLine {
+        bitvisor_command_line_uuid: UEFI_BITVISOR_COMMAND_LINE_UUID,
+        command_line: command_line.as_bytes_with_nul().as_ptr(),
+        command_line_size: command_line.as_str().len(),
+    };
+
+    /* The list of system information is terminated by a null pointer */
+    let system_info_entries: [Option<*const usize>; MAX_SYSTEM_INFO_ENTRIES] = [
+        Some(&boot_info as *const BitVisorBoot as *const usize),
+        Some(&bitvisor_disconnect_info as *const BitVisorDisconnectController as *const usize),
+        Some(&acpi_table as *const AcpiTable as *const usize),
+        dtb_address.map(|_| &dtb_table as *const DtbTable as *const usize),
+        (!command_line.is_empty())
+            .then_some(&command_line_info as *const BitVisorCommandLine as *const usize),
+        boot_log_info
+            .as_ref()
+            .map(|b| b as *const BitVisorBootLog as *const usize),
+        graphics_output_info
+            .as_ref()
+            .map(|g| g as *const BitVisorGraphicsOutput as *const usize),
+    ];
+    let mut system_info_pointers: [*const usize; MAX_SYSTEM_INFO_ENTRIES + 1] =
+        [core::ptr::null(); MAX_SYSTEM_INFO_ENTRIES + 1];
+    for (pointer, entry) in system_info_pointers
+        .iter_mut()
+        .zip(system_info_entries.iter().flatten())
+    {
+        *pointer = *entry;
+    }
+
+    let system_info_ptr = system_info_pointers.as_ptr() as usize;
 
     /*let result: i32;
     unsafe {
@@ -194,22 +456,197 @@ extern "C" fn efi_main(image_handle: EfiHandle, system_table: *mut EfiSystemTabl
     let result = unsafe { entry_fn(image_handle, system_table, system_info_ptr) };
 
     if result == 0 {
-        println!("BootFailed!");
+        error!("BootFailed!");
+        save_boot_log(root_protocol);
         return EfiStatus::EfiLoadError;
     }
 
-    b_s.free_memory(physical_address, 0x10)
-        .expect("failed to free memory");
+    loaded_image.free(b_s);
+    image_buffer.free(b_s);
 
     if let Err(e) = file::EfiFileProtocol::close_file(bitvisor_protocol) {
-        println!("Failed to close BitVisor Protocol: {:?}", e);
+        warn!("Failed to close BitVisor Protocol: {:?}", e);
     }
     if let Err(e) = file::EfiFileProtocol::close_file(root_protocol) {
-        println!("Failed to close RootProtocol: {:?}", e);
+        warn!("Failed to close RootProtocol: {:?}", e);
     }
     EfiStatus::EfiSuccess
 }
 
+/// Search the boot directory for the hypervisor images
+///
+/// Files named like `bitvisor*.elf` or `bitvisor*.elf.gz` are added to `menu`,
+/// and `bitvisor.elf` becomes the default entry if it exists.
+///
+/// # Arguments
+/// * `root_protocol` - the root directory of the boot volume
+/// * `b_s` - EfiBootService
+/// * `menu` - the boot menu to add the found images
+fn scan_hypervisor_images(
+    root_protocol: &EfiFileProtocol,
+    b_s: &EfiBootServices,
+    menu: &mut BootMenu,
+) {
+    let mut directory_path = [0u16; BOOT_DIRECTORY.len() + 1];
+    for (i, m) in BOOT_DIRECTORY.encode_utf16().enumerate() {
+        directory_path[i] = m;
+    }
+    let Ok(directory) = file::EfiFileProtocol::open_file(root_protocol, &directory_path) else {
+        return;
+    };
+    let entries = match directory.read_dir(b_s) {
+        Ok(e) => e,
+        Err(e) => {
+            warn!("Failed to read {}: {:?}", BOOT_DIRECTORY, e);
+            let _ = file::EfiFileProtocol::close_file(directory);
+            return;
+        }
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => {
+                warn!("Failed to read an entry of {}: {:?}", BOOT_DIRECTORY, e);
+                break;
+            }
+        };
+        if entry.is_directory() {
+            continue;
+        }
+        let mut name_buffer = [0u8; MAX_PATH_LENGTH];
+        let Some(name) = to_ascii(entry.file_name(), &mut name_buffer) else {
+            continue;
+        };
+        let is_default = name.eq_ignore_ascii_case(DEFAULT_IMAGE_NAME);
+        let is_candidate = name.len() > IMAGE_NAME_PREFIX.len()
+            && name[..IMAGE_NAME_PREFIX.len()].eq_ignore_ascii_case(IMAGE_NAME_PREFIX)
+            && IMAGE_NAME_SUFFIXES.iter().any(|suffix| {
+                name.len() >= suffix.len()
+                    && name[name.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+            });
+        if !is_candidate {
+            continue;
+        }
+        if menu.is_full() {
+            warn!("Too many hypervisor images, skip {}", name);
+            continue;
+        }
+        if menu.add_entry(
+            boot_file_path(entry.file_name().iter().copied()),
+            is_default,
+        ) {
+            println!("Found hypervisor image: {}\\{}", BOOT_DIRECTORY, name);
+        }
+    }
+    let _ = file::EfiFileProtocol::close_file(directory);
+}
+
+/// Read the boot configuration file in the boot directory
+///
+/// # Result
+/// The settings in the file. If the file is missing or malformed, BootConfig without settings
+fn read_boot_config(root_protocol: &EfiFileProtocol, b_s: &EfiBootServices) -> BootConfig {
+    let mut path = [0u16; MAX_PATH_LENGTH];
+    menu::set_path(&mut path, boot_file_path(CONFIG_NAME.encode_utf16()));
+    let Ok(config_protocol) = file::EfiFileProtocol::open_file(root_protocol, &path) else {
+        debug!(
+            "{}\\{} is not found, use the defaults",
+            BOOT_DIRECTORY, CONFIG_NAME
+        );
+        return BootConfig::new();
+    };
+    let config = match config_protocol.read_to_end(b_s) {
+        Ok(buffer) => {
+            let result = BootConfig::parse(buffer.as_slice());
+            buffer.free(b_s);
+            match result {
+                Ok(c) => {
+                    println!("Load {}\\{}", BOOT_DIRECTORY, CONFIG_NAME);
+                    c
+                }
+                Err(e) => {
+                    warn!(
+                        "Ignore {}\\{}: {:?} at line {}, use the defaults",
+                        BOOT_DIRECTORY, CONFIG_NAME, e.kind, e.line
+                    );
+                    BootConfig::new()
+                }
+            }
+        }
+        Err(e) => {
+            warn!(
+                "Failed to read {}\\{}: {:?}, use the defaults",
+                BOOT_DIRECTORY, CONFIG_NAME, e
+            );
+            BootConfig::new()
+        }
+    };
+    let _ = file::EfiFileProtocol::close_file(config_protocol);
+    config
+}
+
+/// Get the UTF-16 path of `name` in the boot directory
+fn boot_file_path(name: impl Iterator<Item = u16>) -> impl Iterator<Item = u16> {
+    BOOT_DIRECTORY
+        .encode_utf16()
+        .chain("\\".encode_utf16())
+        .chain(name)
+}
+
+/// Read bsdriver.efi from `bsdriver_path` of the configuration or the boot directory
+#[cfg(feature = "bsdriver-file")]
+fn read_bsdriver(root_protocol: &EfiFileProtocol, b_s: &EfiBootServices, config: &BootConfig) {
+    let mut path = [0u16; MAX_PATH_LENGTH];
+    let is_set = match config.bsdriver_path() {
+        Some(p) => menu::set_path(&mut path, p.encode_utf16()),
+        None => menu::set_path(
+            &mut path,
+            boot_file_path(bsdriver::BSDRIVER_NAME.encode_utf16()),
+        ),
+    };
+    if !is_set {
+        warn!("The path of bsdriver.efi is too long");
+        return;
+    }
+    match bsdriver::read_bsdriver(root_protocol, b_s, &path) {
+        Ok(()) => println!("Load {}", Utf16Str(&path)),
+        Err(e) => warn!("Failed to read {}: {:?}", Utf16Str(&path), e),
+    }
+}
+
+/// Write the boot log to the boot directory unless it is disabled by the configuration
+fn save_boot_log(root_protocol: &EfiFileProtocol) {
+    if !unsafe { SAVE_BOOT_LOG } {
+        return;
+    }
+    let mut path = [0u16; MAX_PATH_LENGTH];
+    menu::set_path(&mut path, boot_file_path(BOOT_LOG_NAME.encode_utf16()));
+    if let Err(e) = bootlog::save(root_protocol, &path) {
+        warn!(
+            "Failed to save {}\\{}: {:?}",
+            BOOT_DIRECTORY, BOOT_LOG_NAME, e
+        );
+    }
+}
+
+/// Convert the UTF-16 file name into ASCII
+///
+/// # Result
+/// If the name consists of ASCII characters and fits in `buffer`, Some(name), otherwise None
+fn to_ascii<'a>(name: &[u16], buffer: &'a mut [u8]) -> Option<&'a str> {
+    if name.len() > buffer.len() {
+        return None;
+    }
+    for (i, &c) in name.iter().enumerate() {
+        if c >= 0x80 {
+            return None;
+        }
+        buffer[i] = c as u8;
+    }
+    core::str::from_utf8(&buffer[..name.len()]).ok()
+}
+
 fn detect_dtb(system_table: &EfiSystemTable) -> Option<NonZeroUsize> {
     for i in 0..system_table.num_table_entries {
         let table = unsafe {
@@ -217,9 +654,9 @@ fn detect_dtb(system_table: &EfiSystemTable) -> Option<NonZeroUsize> {
                 + i * core::mem::size_of::<EfiConfigurationTable>())
                 as *const EfiConfigurationTable)
         };
-        pr_debug!("GUID: {:#X?}", table.vendor_guid);
+        debug!("GUID: {:#X?}", table.vendor_guid);
         if table.vendor_guid == EFI_DTB_TABLE_GUID {
-            pr_debug!("Detect DTB");
+            debug!("Detect DTB");
             return NonZeroUsize::new(table.vendor_table);
         }
     }
@@ -228,18 +665,63 @@ fn detect_dtb(system_table: &EfiSystemTable) -> Option<NonZeroUsize> {
 
 extern "efiapi" fn acpi_table_mod(signature: u32, tableaddr: u64) -> EfiStatus {
     println!("fn acpi_table_mod is called by bitvisor.elf");
+    if !unsafe { ACPI_TABLE_MOD_ENABLED } {
+        warn!("ACPI table modification is disabled by noacpimod");
+        return EfiStatus::EfiUnsupported;
+    }
+    if unsafe { LOAD_BSDRIVER } {
+        #[cfg(any(feature = "bsdriver-embed", feature = "bsdriver-file"))]
+        match call_bsdriver(signature, tableaddr) {
+            Some(status) => return status,
+            None => warn!("bsdriver is not available, use the native implementation"),
+        }
+        #[cfg(not(any(feature = "bsdriver-embed", feature = "bsdriver-file")))]
+        debug!("bsdriver is not built into the loader, use the native implementation");
+    }
+    let system_table = unsafe { &*SYSTEM_TABLE_REF };
+    let boot_service = unsafe { &*BOOT_SERVICES };
+    match acpi::modify_acpi_table(boot_service, system_table, signature, tableaddr as usize) {
+        Ok(()) => EfiStatus::EfiSuccess,
+        Err(e) => {
+            warn!("Failed to modify the ACPI table: {:?}", e);
+            e
+        }
+    }
+}
+
+/// Call acpi_table_mod of bsdriver
+///
+/// # Result
+/// If bsdriver is loaded and started, Some(the status of acpi_table_mod), otherwise None
+#[cfg(any(feature = "bsdriver-embed", feature = "bsdriver-file"))]
+fn call_bsdriver(signature: u32, tableaddr: u64) -> Option<EfiStatus> {
     let image_handle = unsafe { IMAGE_HANDLE_REF };
     let system_table = unsafe { SYSTEM_TABLE_REF } as *mut EfiSystemTable;
     let boot_service = unsafe { &*BOOT_SERVICES };
-    if let Some(bsdriver) = load_bsdriver(image_handle, boot_service) {
-        return (bsdriver.acpi_table_mod)(system_table, signature, tableaddr);
-    } else {
-        EfiStatus::EfiLoadError
-    }
+    bsdriver::load_bsdriver(image_handle, boot_service)
+        .and_then(|d| d.acpi_table_mod)
+        .map(|acpi_table_mod| acpi_table_mod(system_table, signature, tableaddr))
 }
 
+#[cfg(not(test))]
 #[panic_handler]
 pub fn panic(info: &core::panic::PanicInfo) -> ! {
-    println!("\n\nBoot Loader Panic: {}", info);
+    use core::sync::atomic::{AtomicBool, Ordering};
+    console::print_with_color(
+        uefi::output::EFI_LIGHTRED,
+        format_args!("\n\nBoot Loader Panic: {}\n", info),
+    );
+    /* Do not save the log again if saving it panics */
+    static IS_PANICKING: AtomicBool = AtomicBool::new(false);
+    let b_s = unsafe { BOOT_SERVICES };
+    if !b_s.is_null() && !IS_PANICKING.swap(true, Ordering::Relaxed) {
+        let image_handle = unsafe { IMAGE_HANDLE_REF };
+        if let Ok(root_protocol) =
+            file::EfiFileProtocol::open_root_dir(image_handle, unsafe { &*b_s })
+        {
+            save_boot_log(root_protocol);
+            let _ = file::EfiFileProtocol::close_file(root_protocol);
+        }
+    }
     cpu::halt_loop();
 }
@@ -0,0 +1,165 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Boot Menu
+//!
+//! List the hypervisor images and let the user choose one with the arrow keys.
+//! The default image is booted when no key is pressed until the timeout.
+//!
+
+use crate::console;
+use crate::uefi::boot_service::EfiBootServices;
+use crate::uefi::input::{CHAR_CARRIAGE_RETURN, SCAN_DOWN, SCAN_UP};
+use core::fmt;
+
+pub const MAX_PATH_LENGTH: usize = 256;
+pub const MAX_MENU_ENTRIES: usize = 8;
+
+/// The width cleared before redrawing the selected entry
+const STATUS_LINE_WIDTH: usize = 79;
+
+pub struct BootMenu {
+    paths: [[u16; MAX_PATH_LENGTH]; MAX_MENU_ENTRIES],
+    num_of_entries: usize,
+    default_index: usize,
+}
+
+impl BootMenu {
+    pub const fn new() -> Self {
+        Self {
+            paths: [[0; MAX_PATH_LENGTH]; MAX_MENU_ENTRIES],
+            num_of_entries: 0,
+            default_index: 0,
+        }
+    }
+
+    /// Add the path of an image
+    ///
+    /// # Arguments
+    /// * `path` - the path from the root of the ESP in UTF-16
+    /// * `is_default` - boot this entry when the timeout expires
+    ///
+    /// # Result
+    /// If the entry is added, true, if the menu is full or the path is too long, false
+    pub fn add_entry(&mut self, path: impl Iterator<Item = u16>, is_default: bool) -> bool {
+        if self.num_of_entries >= MAX_MENU_ENTRIES
+            || !set_path(&mut self.paths[self.num_of_entries], path)
+        {
+            return false;
+        }
+        if is_default {
+            self.default_index = self.num_of_entries;
+        }
+        self.num_of_entries += 1;
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_of_entries == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.num_of_entries >= MAX_MENU_ENTRIES
+    }
+
+    /// Get the null-terminated path of the entry
+    pub fn get_path(&self, index: usize) -> &[u16; MAX_PATH_LENGTH] {
+        &self.paths[index]
+    }
+
+    /// Show the menu and wait for the selection
+    ///
+    /// Up/Down or the number keys move the selection, and Enter boots it.
+    /// Any key stops the countdown.
+    ///
+    /// # Arguments
+    /// * `b_s` - EfiBootService
+    /// * `timeout` - seconds until the default entry is booted, 0 boots it immediately
+    ///
+    /// # Result
+    /// The index of the selected entry
+    pub fn select(&self, b_s: &EfiBootServices, timeout: usize) -> usize {
+        let mut selected = self.default_index;
+        if self.num_of_entries <= 1 || timeout == 0 {
+            return selected;
+        }
+
+        println!("Select the hypervisor image with Up/Down and Enter:");
+        for (i, path) in self.paths[..self.num_of_entries].iter().enumerate() {
+            println!("  {}: {}", i + 1, Utf16Str(path));
+        }
+
+        let mut remaining = Some(timeout);
+        loop {
+            print!("\r{:1$}\r", "", STATUS_LINE_WIDTH);
+            print!("> {}: {}", selected + 1, Utf16Str(&self.paths[selected]));
+            if let Some(r) = remaining {
+                print!(" (boot in {}s)", r);
+            }
+
+            let Some(key) = console::wait_for_key(b_s, remaining.map(|_| 1000)) else {
+                match remaining {
+                    Some(r) if r > 1 => {
+                        remaining = Some(r - 1);
+                        continue;
+                    }
+                    /* The timeout expired or the console has no input */
+                    _ => break,
+                }
+            };
+            remaining = None;
+            match (key.scan_code, key.unicode_char) {
+                (SCAN_UP, _) => {
+                    selected = (selected + self.num_of_entries - 1) % self.num_of_entries
+                }
+                (SCAN_DOWN, _) => selected = (selected + 1) % self.num_of_entries,
+                (_, CHAR_CARRIAGE_RETURN) => break,
+                (_, c) if (b'1' as u16..=b'9' as u16).contains(&c) => {
+                    let index = (c - b'1' as u16) as usize;
+                    if index < self.num_of_entries {
+                        selected = index;
+                    }
+                }
+                _ => {}
+            }
+        }
+        println!("");
+        selected
+    }
+}
+
+/// Store the null-terminated `source` to `path`
+///
+/// # Result
+/// If the path fits in `path`, true, otherwise false
+pub fn set_path(path: &mut [u16; MAX_PATH_LENGTH], source: impl Iterator<Item = u16>) -> bool {
+    let mut length = 0;
+    for m in source {
+        if length >= MAX_PATH_LENGTH - 1 {
+            path[0] = 0;
+            return false;
+        }
+        path[length] = m;
+        length += 1;
+    }
+    path[length] = 0;
+    true
+}
+
+/// Display the UTF-16 string until the null character
+pub struct Utf16Str<'a>(pub &'a [u16]);
+
+impl<'a> fmt::Display for Utf16Str<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let length = self.0.iter().position(|&c| c == 0).unwrap_or(self.0.len());
+        for c in char::decode_utf16(self.0[..length].iter().copied()) {
+            fmt::Write::write_char(f, c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Serial Port
+//!
+//! Write to EFI_SERIAL_IO_PROTOCOL, or to a 16550 compatible UART directly
+//! when the firmware does not provide the protocol.
+//!
+
+use crate::cpu::{in_byte, out_byte};
+use crate::uefi::boot_service::EfiBootServices;
+use crate::uefi::serial_io::EfiSerialIoProtocol;
+use crate::uefi::EfiStatus;
+
+/// The I/O port of COM1
+pub const DEFAULT_UART_PORT: u16 = 0x3F8;
+
+const UART_CLOCK: u32 = 115200;
+const UART_BAUD_RATE: u32 = 115200;
+
+/* The offsets of the 16550 registers */
+const UART_THR: u16 = 0;
+const UART_DLL: u16 = 0;
+const UART_IER: u16 = 1;
+const UART_DLM: u16 = 1;
+const UART_FCR: u16 = 2;
+const UART_LCR: u16 = 3;
+const UART_MCR: u16 = 4;
+const UART_LSR: u16 = 5;
+const UART_SCR: u16 = 7;
+
+const UART_LCR_8N1: u8 = 0x03;
+const UART_LCR_DLAB: u8 = 0x80;
+const UART_FCR_ENABLE_AND_CLEAR: u8 = 0x07;
+const UART_MCR_DTR_RTS: u8 = 0x03;
+const UART_LSR_THRE: u8 = 0x20;
+
+/// The number of polls before giving up a byte, the UART may be not connected
+const UART_WAIT_LIMIT: usize = 0x10000;
+
+#[derive(Clone, Copy)]
+pub enum SerialPort {
+    Uefi(&'static EfiSerialIoProtocol),
+    Uart16550(u16),
+}
+
+impl SerialPort {
+    /// Open the serial port
+    ///
+    /// # Arguments
+    /// * `b_s` - EfiBootService to locate EFI_SERIAL_IO_PROTOCOL
+    /// * `uart_port` - the I/O port of the UART used when the protocol is not found
+    ///
+    /// # Result
+    /// If the protocol or the UART is found, Ok(SerialPort), otherwise Err(EfiStatus)
+    pub fn open(b_s: &EfiBootServices, uart_port: u16) -> Result<Self, EfiStatus> {
+        if let Ok(serial_io) = EfiSerialIoProtocol::locate(b_s) {
+            return Ok(Self::Uefi(serial_io));
+        }
+        Self::open_uart(uart_port)
+    }
+
+    /// Initialize the 16550 UART at `uart_port` as 115200 bps, 8N1
+    ///
+    /// # Result
+    /// If the UART exists, Ok(SerialPort), otherwise Err(EfiStatus::EfiNotFound)
+    pub fn open_uart(uart_port: u16) -> Result<Self, EfiStatus> {
+        /* Check the scratch register, reading a missing port returns 0xFF */
+        for pattern in [0x55, 0xAA] {
+            out_byte(uart_port + UART_SCR, pattern);
+            if in_byte(uart_port + UART_SCR) != pattern {
+                return Err(EfiStatus::EfiNotFound);
+            }
+        }
+        let divisor = (UART_CLOCK / UART_BAUD_RATE) as u16;
+        out_byte(uart_port + UART_IER, 0);
+        out_byte(uart_port + UART_LCR, UART_LCR_DLAB);
+        out_byte(uart_port + UART_DLL, divisor as u8);
+        out_byte(uart_port + UART_DLM, (divisor >> 8) as u8);
+        out_byte(uart_port + UART_LCR, UART_LCR_8N1);
+        out_byte(uart_port + UART_FCR, UART_FCR_ENABLE_AND_CLEAR);
+        out_byte(uart_port + UART_MCR, UART_MCR_DTR_RTS);
+        Ok(Self::Uart16550(uart_port))
+    }
+
+    /// Write the string, LF is converted to CR LF
+    pub fn write_str(&self, string: &str) -> Result<(), EfiStatus> {
+        for (i, line) in string.split('\n').enumerate() {
+            if i != 0 {
+                self.write_bytes(b"\r\n")?;
+            }
+            self.write_bytes(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&self, bytes: &[u8]) -> Result<(), EfiStatus> {
+        match self {
+            Self::Uefi(serial_io) => serial_io.write(bytes),
+            Self::Uart16550(port) => {
+                for &b in bytes {
+                    let mut wait = 0;
+                    while (in_byte(port + UART_LSR) & UART_LSR_THRE) == 0 {
+                        wait += 1;
+                        if wait >= UART_WAIT_LIMIT {
+                            return Err(EfiStatus::EfiTimeout);
+                        }
+                        core::hint::spin_loop();
+                    }
+                    out_byte(port + UART_THR, b);
+                }
+                Ok(())
+            }
+        }
+    }
+}
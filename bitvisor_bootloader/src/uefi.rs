@@ -15,12 +15,18 @@
 
 pub mod boot_service;
 pub mod file;
+pub mod graphics_output;
+pub mod input;
 pub mod loaded_image;
+pub mod memory_attribute;
 pub mod output;
+pub mod serial_io;
 pub mod acpi_table;
+pub mod acpi_table_protocol;
 pub mod dtb;
 
 pub type EfiHandle = usize;
+pub type EfiEvent = usize;
 
 macro_rules! efi_error {
     ($code:expr) => {
@@ -97,7 +103,7 @@ pub struct EfiSystemTable {
     pub firmware_vendor: usize,
     pub firmware_version: u32,
     pub console_input_handler: EfiHandle,
-    pub console_input_protocol: usize,
+    pub console_input_protocol: *const input::EfiInputProtocol,
     pub console_output_handler: EfiHandle,
     pub console_output_protocol: *const output::EfiOutputProtocol,
     pub standard_error_handler: EfiHandle,
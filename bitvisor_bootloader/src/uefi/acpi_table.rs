@@ -10,10 +10,14 @@
 //!
 //! Supported ACPI Version 6.4
 
-use core::ptr::read_unaligned;
+use core::ptr::{read_unaligned, write_unaligned};
 
 const RSDP_SIGNATURE: [u8; 8] = *b"RSD PTR ";
 const XSDT_SIGNATURE: [u8; 4] = *b"XSDT";
+const RSDT_SIGNATURE: [u8; 4] = *b"RSDT";
+
+/// The offset of `checksum` in the System Description Table Header
+const SDT_CHECKSUM_OFFSET: usize = 9;
 
 pub const XSDT_STRUCT_SIZE: usize = core::mem::size_of::<XSDT>();
 
@@ -54,6 +58,7 @@ pub struct GeneralAddressStructure {
 pub enum AcpiError {
     InvalidSignature,
     InvalidAddress,
+    InvalidLength,
     TableNotFound,
 }
 
@@ -89,6 +94,20 @@ impl GeneralAddressStructure {
     }
 }
 
+/// Get the number of the entries of XSDT or RSDT
+///
+/// # Arguments
+/// * `table` - XSDT or RSDT
+/// * `entry_size` - the size of an entry, 8 for XSDT and 4 for RSDT
+///
+/// # Result
+/// If the length covers the header, Some(the number of the entries), otherwise None
+fn get_number_of_entries(table: &XSDT, entry_size: usize) -> Option<usize> {
+    (table.length as usize)
+        .checked_sub(XSDT_STRUCT_SIZE)
+        .map(|size| size / entry_size)
+}
+
 pub fn get_acpi_table(rsdp_address: usize, signature: &[u8; 4]) -> Result<usize, AcpiError> {
     let rsdp = unsafe { read_unaligned(rsdp_address as *const RSDP) };
     if rsdp.signature != RSDP_SIGNATURE {
@@ -103,7 +122,10 @@ pub fn get_acpi_table(rsdp_address: usize, signature: &[u8; 4]) -> Result<usize,
         return Err(AcpiError::InvalidSignature);
     }
 
-    for table_index in 0..((xsdt.length as usize - XSDT_STRUCT_SIZE) >> 3) {
+    let Some(num_of_entries) = get_number_of_entries(xsdt, 8) else {
+        return Err(AcpiError::InvalidLength);
+    };
+    for table_index in 0..num_of_entries {
         let table_address = unsafe {
             read_unaligned(
                 (rsdp.xsdt_address as usize + XSDT_STRUCT_SIZE + (table_index << 3)) as *const u64,
@@ -117,3 +139,83 @@ pub fn get_acpi_table(rsdp_address: usize, signature: &[u8; 4]) -> Result<usize,
 
     Err(AcpiError::TableNotFound)
 }
+
+/// Get the length of the System Description Table including the header
+pub fn get_table_length(table_address: usize) -> usize {
+    unsafe { read_unaligned((table_address + 4) as *const u32) as usize }
+}
+
+/// Set the checksum so that the sum of the whole table becomes zero
+pub fn update_checksum(table_address: usize) {
+    let table = unsafe {
+        core::slice::from_raw_parts_mut(table_address as *mut u8, get_table_length(table_address))
+    };
+    table[SDT_CHECKSUM_OFFSET] = 0;
+    let sum = table.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    table[SDT_CHECKSUM_OFFSET] = 0u8.wrapping_sub(sum);
+}
+
+/// Find the RSDT entry which points to `table_address`
+///
+/// RSDT has the same header as XSDT, and its entries are 32-bit addresses.
+fn find_rsdt_entry(rsdt_address: usize, table_address: usize) -> Option<*mut u32> {
+    let rsdt = unsafe { &*(rsdt_address as *const XSDT) };
+    if rsdt.signature != RSDT_SIGNATURE {
+        return None;
+    }
+    for table_index in 0..get_number_of_entries(rsdt, 4)? {
+        let entry = (rsdt_address + XSDT_STRUCT_SIZE + (table_index << 2)) as *mut u32;
+        if unsafe { read_unaligned(entry) } as usize == table_address {
+            return Some(entry);
+        }
+    }
+    None
+}
+
+/// Replace the XSDT entry of the table which has `signature` with `new_table_address`
+///
+/// If RSDT also lists the old table, its entry is replaced too, so that the OS which reads
+/// RSDT does not see the old table.
+/// The checksums of the new table, XSDT and RSDT are updated.
+///
+/// # Result
+/// If the entry is replaced, Ok(the address of the old table), otherwise Err(AcpiError)
+pub fn replace_acpi_table(
+    rsdp_address: usize,
+    signature: &[u8; 4],
+    new_table_address: usize,
+) -> Result<usize, AcpiError> {
+    let old_table_address = get_acpi_table(rsdp_address, signature)?;
+    let rsdp = unsafe { read_unaligned(rsdp_address as *const RSDP) };
+    let xsdt_address = rsdp.xsdt_address as usize;
+    let xsdt = unsafe { &*(xsdt_address as *const XSDT) };
+    let rsdt_address = rsdp.rsdt_address as usize;
+    let rsdt_entry = if rsdt_address != 0 {
+        find_rsdt_entry(rsdt_address, old_table_address)
+    } else {
+        None
+    };
+    if rsdt_entry.is_some() && u32::try_from(new_table_address).is_err() {
+        /* Do not leave RSDT pointing to the old table */
+        return Err(AcpiError::InvalidAddress);
+    }
+
+    let Some(num_of_entries) = get_number_of_entries(xsdt, 8) else {
+        return Err(AcpiError::InvalidLength);
+    };
+    for table_index in 0..num_of_entries {
+        let entry = (xsdt_address + XSDT_STRUCT_SIZE + (table_index << 3)) as *mut u64;
+        if unsafe { read_unaligned(entry) } as usize == old_table_address {
+            unsafe { write_unaligned(entry, new_table_address as u64) };
+            update_checksum(new_table_address);
+            update_checksum(xsdt_address);
+            if let Some(rsdt_entry) = rsdt_entry {
+                unsafe { write_unaligned(rsdt_entry, new_table_address as u32) };
+                update_checksum(rsdt_address);
+            }
+            return Ok(old_table_address);
+        }
+    }
+
+    Err(AcpiError::TableNotFound)
+}
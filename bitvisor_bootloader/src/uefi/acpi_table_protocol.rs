@@ -0,0 +1,125 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! EFI ACPI Table Protocol and EFI ACPI SDT Protocol
+//!
+
+use super::boot_service::EfiBootServices;
+use super::{EfiStatus, Guid};
+
+pub const EFI_ACPI_TABLE_PROTOCOL_GUID: Guid = Guid {
+    d1: 0xFFE06BDD,
+    d2: 0x6107,
+    d3: 0x46A6,
+    d4: [0x7B, 0xB2, 0x5A, 0x9C, 0x7E, 0xC5, 0x27, 0x5C],
+};
+
+pub const EFI_ACPI_SDT_PROTOCOL_GUID: Guid = Guid {
+    d1: 0xEB97088E,
+    d2: 0xCFDF,
+    d3: 0x49C6,
+    d4: [0xBE, 0x4B, 0xD9, 0x06, 0xA5, 0xB2, 0x0E, 0x86],
+};
+
+#[repr(C)]
+pub struct EfiAcpiTableProtocol {
+    install_acpi_table: extern "efiapi" fn(
+        this: *const Self,
+        acpi_table_buffer: *const u8,
+        acpi_table_buffer_size: usize,
+        table_key: *mut usize,
+    ) -> EfiStatus,
+    uninstall_acpi_table: extern "efiapi" fn(this: *const Self, table_key: usize) -> EfiStatus,
+}
+
+impl EfiAcpiTableProtocol {
+    /// Locate the protocol
+    ///
+    /// # Result
+    /// If the firmware has the protocol, Ok(protocol), otherwise Err(EfiStatus)
+    pub fn locate(b_s: &EfiBootServices) -> Result<&'static Self, EfiStatus> {
+        let interface = b_s.locate_protocol(&EFI_ACPI_TABLE_PROTOCOL_GUID)?;
+        Ok(unsafe { &*(interface as *const Self) })
+    }
+
+    /// Install the copy of the table, RSDT/XSDT and the checksum are updated by the firmware
+    ///
+    /// # Arguments
+    /// * `table` - the whole table including the header
+    ///
+    /// # Result
+    /// If the table is installed, Ok(the key of the table), otherwise Err(EfiStatus)
+    pub fn install_acpi_table(&self, table: &[u8]) -> Result<usize, EfiStatus> {
+        let mut table_key = 0;
+        let status = (self.install_acpi_table)(self, table.as_ptr(), table.len(), &mut table_key);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(table_key)
+    }
+
+    /// Remove the table installed with `table_key`
+    pub fn uninstall_acpi_table(&self, table_key: usize) -> Result<(), EfiStatus> {
+        let status = (self.uninstall_acpi_table)(self, table_key);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct EfiAcpiSdtProtocol {
+    acpi_version: u32,
+    get_acpi_table: extern "efiapi" fn(
+        index: usize,
+        table: *mut *const u8,
+        version: *mut u32,
+        table_key: *mut usize,
+    ) -> EfiStatus,
+    register_notify: usize,
+    open: usize,
+    open_sdt: usize,
+    close: usize,
+    get_child: usize,
+    get_option: usize,
+    set_option: usize,
+    find_path: usize,
+}
+
+impl EfiAcpiSdtProtocol {
+    /// Locate the protocol
+    ///
+    /// # Result
+    /// If the firmware has the protocol, Ok(protocol), otherwise Err(EfiStatus)
+    pub fn locate(b_s: &EfiBootServices) -> Result<&'static Self, EfiStatus> {
+        let interface = b_s.locate_protocol(&EFI_ACPI_SDT_PROTOCOL_GUID)?;
+        Ok(unsafe { &*(interface as *const Self) })
+    }
+
+    /// Find the installed table which has `signature`
+    ///
+    /// # Result
+    /// If the table is found, Ok((the table key for EfiAcpiTableProtocol, the table address)),
+    /// otherwise Err(EfiStatus::EfiNotFound)
+    pub fn find_acpi_table(&self, signature: &[u8; 4]) -> Result<(usize, usize), EfiStatus> {
+        for index in 0.. {
+            let mut table: *const u8 = core::ptr::null();
+            let mut version = 0u32;
+            let mut table_key = 0;
+            let status = (self.get_acpi_table)(index, &mut table, &mut version, &mut table_key);
+            if status != EfiStatus::EfiSuccess {
+                break;
+            }
+            if !table.is_null() && unsafe { *(table as *const [u8; 4]) } == *signature {
+                return Ok((table_key, table as usize));
+            }
+        }
+        Err(EfiStatus::EfiNotFound)
+    }
+}
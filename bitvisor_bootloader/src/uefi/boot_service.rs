@@ -9,17 +9,30 @@
 //! UEFI Boot Services
 //!
 
+mod event_service;
+mod image_service;
 mod memory_service;
+mod misc_service;
+mod protocol_service;
 
+pub use event_service::*;
 pub use memory_service::*;
+pub use protocol_service::*;
 
-use super::{EfiHandle, EfiStatus, EfiTableHeader, Guid};
+use super::{EfiEvent, EfiHandle, EfiStatus, EfiTableHeader, Guid};
 
+/// EFI_BOOT_SERVICES of UEFI 2.9
+///
+/// The entries are private, the safe wrappers are implemented in the service modules.
 #[repr(C)]
 pub struct EfiBootServices {
     efi_table_header: EfiTableHeader,
-    raise_tpl: usize,
-    restore_tpl: usize,
+
+    /* Task Priority Services */
+    raise_tpl: extern "efiapi" fn(new_tpl: usize) -> usize,
+    restore_tpl: extern "efiapi" fn(old_tpl: usize),
+
+    /* Memory Services */
     allocate_pages: extern "efiapi" fn(
         allocate_type: EfiAllocateType,
         memory_type: EfiMemoryType,
@@ -40,54 +53,121 @@ pub struct EfiBootServices {
     _allocate_pool:
         extern "efiapi" fn(pool_type: EfiMemoryType, size: usize, memory: *mut usize) -> EfiStatus,
     _free_pool: extern "efiapi" fn(memory: usize) -> EfiStatus,
-    create_event: usize,
-    set_timer: usize,
-    wait_for_event: usize,
-    signal_event: usize,
-    close_event: usize,
-    check_event: usize,
-    install_protocol_interface: usize,
-    reinstall_protocol_interface: usize,
-    uninstall_protocol_interface: usize,
-    handle_protocol: usize,
+
+    /* Event & Timer Services */
+    create_event: extern "efiapi" fn(
+        event_type: u32,
+        notify_tpl: usize,
+        notify_function: Option<EfiEventNotify>,
+        notify_context: usize,
+        event: *mut EfiEvent,
+    ) -> EfiStatus,
+    set_timer: extern "efiapi" fn(
+        event: EfiEvent,
+        timer_type: EfiTimerDelay,
+        trigger_time: u64,
+    ) -> EfiStatus,
+    wait_for_event: extern "efiapi" fn(
+        number_of_events: usize,
+        event: *const EfiEvent,
+        index: *mut usize,
+    ) -> EfiStatus,
+    signal_event: extern "efiapi" fn(event: EfiEvent) -> EfiStatus,
+    close_event: extern "efiapi" fn(event: EfiEvent) -> EfiStatus,
+    check_event: extern "efiapi" fn(event: EfiEvent) -> EfiStatus,
+
+    /* Protocol Handler Services */
+    install_protocol_interface: extern "efiapi" fn(
+        handle: *mut EfiHandle,
+        protocol: *const Guid,
+        interface_type: EfiInterfaceType,
+        interface: *const usize,
+    ) -> EfiStatus,
+    reinstall_protocol_interface: extern "efiapi" fn(
+        handle: EfiHandle,
+        protocol: *const Guid,
+        old_interface: *const usize,
+        new_interface: *const usize,
+    ) -> EfiStatus,
+    uninstall_protocol_interface: extern "efiapi" fn(
+        handle: EfiHandle,
+        protocol: *const Guid,
+        interface: *const usize,
+    ) -> EfiStatus,
+    handle_protocol: extern "efiapi" fn(
+        handle: EfiHandle,
+        protocol: *const Guid,
+        interface: *mut *const usize,
+    ) -> EfiStatus,
     reserved: usize,
-    register_protocol_notify: usize,
-    pub locate_handle: extern "efiapi" fn(
-            efi_locate_search_type: i32,
-            protocol: *const Guid,
-            search_key: *const usize,
-            buffer_size: *mut usize,
-            buffer: *mut EfiHandle
-    ) -> EfiStatus,
-    locate_device_path: usize,
-    install_configuration_table: usize,
-    pub load_image: extern "efiapi" fn(
-        boot_policy: u8,
+    register_protocol_notify: extern "efiapi" fn(
+        protocol: *const Guid,
+        event: EfiEvent,
+        registration: *mut *const usize,
+    ) -> EfiStatus,
+    locate_handle: extern "efiapi" fn(
+        search_type: EfiLocateSearchType,
+        protocol: *const Guid,
+        search_key: *const usize,
+        buffer_size: *mut usize,
+        buffer: *mut EfiHandle,
+    ) -> EfiStatus,
+    locate_device_path: extern "efiapi" fn(
+        protocol: *const Guid,
+        device_path: *mut *const DevicePathProtocol,
+        device: *mut EfiHandle,
+    ) -> EfiStatus,
+    install_configuration_table:
+        extern "efiapi" fn(guid: *const Guid, table: *const usize) -> EfiStatus,
+
+    /* Image Services */
+    load_image: extern "efiapi" fn(
+        boot_policy: bool,
         parent_image_handle: EfiHandle,
         device_path: *const DevicePathProtocol,
-        out_handle: *mut EfiHandle,
+        source_buffer: *const u8,
+        source_size: usize,
+        image_handle: *mut EfiHandle,
     ) -> EfiStatus,
-    pub start_image:
-        extern "efiapi" fn(handle: EfiHandle, data_size: *mut usize, data: usize) -> EfiStatus,
-    pub exit: extern "efiapi" fn(
+    start_image: extern "efiapi" fn(
+        image_handle: EfiHandle,
+        exit_data_size: *mut usize,
+        exit_data: *mut *const u16,
+    ) -> EfiStatus,
+    exit: extern "efiapi" fn(
         image_handler: EfiHandle,
         exit_status: EfiStatus,
         exit_data_size: usize,
         exit_data: *const u16,
     ) -> EfiStatus,
-    unload_image: usize,
-    pub exit_boot_services:
-        extern "efiapi" fn(image_handler: EfiHandle, map_key: usize) -> EfiStatus,
-    get_next_monotonic_count: usize,
-    stall: usize,
-    set_watchdog_timer: usize,
-    connect_controller: usize,
-    pub disconnect_controller: extern "efiapi" fn(
+    unload_image: extern "efiapi" fn(image_handle: EfiHandle) -> EfiStatus,
+    exit_boot_services: extern "efiapi" fn(image_handler: EfiHandle, map_key: usize) -> EfiStatus,
+
+    /* Miscellaneous Services */
+    get_next_monotonic_count: extern "efiapi" fn(count: *mut u64) -> EfiStatus,
+    stall: extern "efiapi" fn(microseconds: usize) -> EfiStatus,
+    set_watchdog_timer: extern "efiapi" fn(
+        timeout: usize,
+        watchdog_code: u64,
+        data_size: usize,
+        watchdog_data: *const u16,
+    ) -> EfiStatus,
+
+    /* Driver Support Services */
+    connect_controller: extern "efiapi" fn(
+        controller_handle: EfiHandle,
+        driver_image_handle: *const EfiHandle,
+        remaining_device_path: *const DevicePathProtocol,
+        recursive: bool,
+    ) -> EfiStatus,
+    disconnect_controller: extern "efiapi" fn(
         handle: EfiHandle,
         driver_image: EfiHandle,
         child: EfiHandle,
     ) -> EfiStatus,
-    pub open_protocol: extern "efiapi" fn(
+
+    /* Open and Close Protocol Services */
+    open_protocol: extern "efiapi" fn(
         handle: EfiHandle,
         protocol: *const Guid,
         interface: *mut *const usize,
@@ -95,27 +175,59 @@ pub struct EfiBootServices {
         controller_handle: EfiHandle,
         attributes: u32,
     ) -> EfiStatus,
-    pub close_protocol: extern "efiapi" fn(
+    close_protocol: extern "efiapi" fn(
         handle: EfiHandle,
         protocol: *const Guid,
         agent_handle: EfiHandle,
         controller_handle: EfiHandle,
     ) -> EfiStatus,
-    open_protocol_information: usize,
-    protocols_per_handle: usize,
-    locate_handle_buffer: usize,
-    pub locate_protocol: extern "efiapi" fn(
+    open_protocol_information: extern "efiapi" fn(
+        handle: EfiHandle,
+        protocol: *const Guid,
+        entry_buffer: *mut *const EfiOpenProtocolInformationEntry,
+        entry_count: *mut usize,
+    ) -> EfiStatus,
+
+    /* Library Services */
+    protocols_per_handle: extern "efiapi" fn(
+        handle: EfiHandle,
+        protocol_buffer: *mut *const *const Guid,
+        protocol_buffer_count: *mut usize,
+    ) -> EfiStatus,
+    locate_handle_buffer: extern "efiapi" fn(
+        search_type: EfiLocateSearchType,
+        protocol: *const Guid,
+        search_key: *const usize,
+        no_handles: *mut usize,
+        buffer: *mut *const EfiHandle,
+    ) -> EfiStatus,
+    locate_protocol: extern "efiapi" fn(
         protocol: *const Guid,
         registration: *const usize,
         interface: *mut *const usize,
     ) -> EfiStatus,
-    install_multiple_protocol_interfaces: usize,
     /// Warning: It will work correctly only for '*-unknown-uefi'
-    pub uninstall_multiple_protocol_interfaces: unsafe extern "C" fn(handle: EfiHandle, ...)-> EfiStatus,
-    calculate_crc32: usize,
+    install_multiple_protocol_interfaces:
+        unsafe extern "C" fn(handle: *mut EfiHandle, ...) -> EfiStatus,
+    /// Warning: It will work correctly only for '*-unknown-uefi'
+    uninstall_multiple_protocol_interfaces:
+        unsafe extern "C" fn(handle: EfiHandle, ...) -> EfiStatus,
+
+    /* 32-bit CRC Services */
+    calculate_crc32:
+        extern "efiapi" fn(data: *const u8, data_size: usize, crc32: *mut u32) -> EfiStatus,
+
+    /* Miscellaneous Services */
     copy_mem: extern "efiapi" fn(destination: usize, source: usize, length: usize),
     set_mem: extern "efiapi" fn(buffer: usize, size: usize, value: u8),
-    create_event_ex: usize,
+    create_event_ex: extern "efiapi" fn(
+        event_type: u32,
+        notify_tpl: usize,
+        notify_function: Option<EfiEventNotify>,
+        notify_context: usize,
+        event_group: *const Guid,
+        event: *mut EfiEvent,
+    ) -> EfiStatus,
 }
 
 pub const EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL: u32 = 0x00000001;
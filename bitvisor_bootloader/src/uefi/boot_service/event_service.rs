@@ -0,0 +1,291 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Event and Timer Services of Boot Service
+//!
+
+use super::EfiBootServices;
+
+use super::super::{EfiEvent, EfiStatus, Guid};
+
+pub const EVT_TIMER: u32 = 0x80000000;
+pub const EVT_RUNTIME: u32 = 0x40000000;
+pub const EVT_NOTIFY_WAIT: u32 = 0x00000100;
+pub const EVT_NOTIFY_SIGNAL: u32 = 0x00000200;
+
+pub const TPL_APPLICATION: usize = 4;
+pub const TPL_CALLBACK: usize = 8;
+pub const TPL_NOTIFY: usize = 16;
+pub const TPL_HIGH_LEVEL: usize = 31;
+
+/// The unit of the trigger time of set_timer
+pub const TIMER_TICKS_PER_SECOND: u64 = 10_000_000;
+const TIMER_TICKS_PER_MICROSECOND: u64 = TIMER_TICKS_PER_SECOND / 1_000_000;
+
+pub type EfiEventNotify = extern "efiapi" fn(event: EfiEvent, context: usize);
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub enum EfiTimerDelay {
+    TimerCancel,
+    TimerPeriodic,
+    TimerRelative,
+}
+
+impl EfiBootServices {
+    /// Raise the task priority level
+    ///
+    /// # Result
+    /// The previous task priority level to pass to [`Self::restore_tpl`]
+    pub fn raise_tpl(&self, new_tpl: usize) -> usize {
+        (self.raise_tpl)(new_tpl)
+    }
+
+    /// Restore the task priority level returned by [`Self::raise_tpl`]
+    pub fn restore_tpl(&self, old_tpl: usize) {
+        (self.restore_tpl)(old_tpl)
+    }
+
+    /// Create an event
+    ///
+    /// # Arguments
+    /// * `event_type` - EVT_TIMER, EVT_NOTIFY_WAIT, EVT_NOTIFY_SIGNAL and so on
+    /// * `notify_tpl` - the task priority level of `notify_function`
+    /// * `notify_function` - the function called when the event is waited or signaled
+    /// * `notify_context` - the argument passed to `notify_function`
+    ///
+    /// # Result
+    /// If the event is created, Ok(event), otherwise Err(EfiStatus)
+    pub fn create_event(
+        &self,
+        event_type: u32,
+        notify_tpl: usize,
+        notify_function: Option<EfiEventNotify>,
+        notify_context: usize,
+    ) -> Result<EfiEvent, EfiStatus> {
+        let mut event: EfiEvent = 0;
+        let status = (self.create_event)(
+            event_type,
+            notify_tpl,
+            notify_function,
+            notify_context,
+            &mut event,
+        );
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(event)
+    }
+
+    /// Create an event in the event group
+    ///
+    /// The arguments are the same as [`Self::create_event`] except `event_group`.
+    ///
+    /// # Result
+    /// If the event is created, Ok(event), otherwise Err(EfiStatus)
+    pub fn create_event_ex(
+        &self,
+        event_type: u32,
+        notify_tpl: usize,
+        notify_function: Option<EfiEventNotify>,
+        notify_context: usize,
+        event_group: &Guid,
+    ) -> Result<EfiEvent, EfiStatus> {
+        let mut event: EfiEvent = 0;
+        let status = (self.create_event_ex)(
+            event_type,
+            notify_tpl,
+            notify_function,
+            notify_context,
+            event_group,
+            &mut event,
+        );
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(event)
+    }
+
+    /// Create a timer event without a notification function
+    ///
+    /// # Result
+    /// If the event is created, Ok(event), otherwise Err(EfiStatus)
+    pub fn create_timer_event(&self) -> Result<EfiEvent, EfiStatus> {
+        self.create_event(EVT_TIMER, TPL_APPLICATION, None, 0)
+    }
+
+    /// Set the type of the timer and the trigger time
+    ///
+    /// # Arguments
+    /// * `event` - the timer event
+    /// * `timer_type` - cancel, periodic or relative
+    /// * `trigger_time` - the time in 100ns units
+    ///
+    /// # Result
+    /// If the timer is set, Ok(()), otherwise Err(EfiStatus)
+    pub fn set_timer_event(
+        &self,
+        event: EfiEvent,
+        timer_type: EfiTimerDelay,
+        trigger_time: u64,
+    ) -> Result<(), EfiStatus> {
+        let status = (self.set_timer)(event, timer_type, trigger_time);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Stop until one of the events is signaled
+    ///
+    /// # Arguments
+    /// * `events` - the events to wait
+    ///
+    /// # Result
+    /// If an event is signaled, Ok(index of the event), otherwise Err(EfiStatus)
+    pub fn wait_for_events(&self, events: &[EfiEvent]) -> Result<usize, EfiStatus> {
+        let mut index = 0;
+        let status = (self.wait_for_event)(events.len(), events.as_ptr(), &mut index);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(index)
+    }
+
+    /// Signal the event, all events in the same group are also signaled
+    pub fn signal_event(&self, event: EfiEvent) -> Result<(), EfiStatus> {
+        let status = (self.signal_event)(event);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Check if the event is signaled without waiting
+    ///
+    /// The event is cleared if it is signaled.
+    ///
+    /// # Result
+    /// If the event is signaled, Ok(true), if it is not signaled, Ok(false), otherwise Err(EfiStatus)
+    pub fn check_event(&self, event: EfiEvent) -> Result<bool, EfiStatus> {
+        match (self.check_event)(event) {
+            EfiStatus::EfiSuccess => Ok(true),
+            EfiStatus::EfiNotReady => Ok(false),
+            status => Err(status),
+        }
+    }
+
+    /// Close the event
+    pub fn close_event(&self, event: EfiEvent) -> Result<(), EfiStatus> {
+        let status = (self.close_event)(event);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Sleep for `microseconds` by waiting for a timer event
+    ///
+    /// Unlike [`Self::stall`], the other events and the timers can run while sleeping.
+    pub fn sleep(&self, microseconds: u64) -> Result<(), EfiStatus> {
+        let timer = Event::new_timer(self)?;
+        timer.set_relative(microseconds)?;
+        timer.wait()
+    }
+
+    /// Busy-wait for `microseconds`
+    pub fn stall(&self, microseconds: usize) -> Result<(), EfiStatus> {
+        let status = (self.stall)(microseconds);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+}
+
+/// The event which is closed when it is dropped
+pub struct Event<'a> {
+    b_s: &'a EfiBootServices,
+    event: EfiEvent,
+}
+
+impl<'a> Event<'a> {
+    /// Create an event
+    ///
+    /// See [`EfiBootServices::create_event`] for the arguments.
+    pub fn new(
+        b_s: &'a EfiBootServices,
+        event_type: u32,
+        notify_tpl: usize,
+        notify_function: Option<EfiEventNotify>,
+        notify_context: usize,
+    ) -> Result<Self, EfiStatus> {
+        let event = b_s.create_event(event_type, notify_tpl, notify_function, notify_context)?;
+        Ok(Self { b_s, event })
+    }
+
+    /// Create a timer event without a notification function, the timer is not started
+    pub fn new_timer(b_s: &'a EfiBootServices) -> Result<Self, EfiStatus> {
+        let event = b_s.create_timer_event()?;
+        Ok(Self { b_s, event })
+    }
+
+    /// Get the raw event to pass it to the firmware
+    pub fn as_raw(&self) -> EfiEvent {
+        self.event
+    }
+
+    /// Set the type of the timer and the trigger time in 100ns units
+    pub fn set_timer(&self, timer_type: EfiTimerDelay, trigger_time: u64) -> Result<(), EfiStatus> {
+        self.b_s
+            .set_timer_event(self.event, timer_type, trigger_time)
+    }
+
+    /// Signal the event once after `microseconds`
+    pub fn set_relative(&self, microseconds: u64) -> Result<(), EfiStatus> {
+        self.set_timer(
+            EfiTimerDelay::TimerRelative,
+            microseconds.saturating_mul(TIMER_TICKS_PER_MICROSECOND),
+        )
+    }
+
+    /// Signal the event every `microseconds`
+    pub fn set_periodic(&self, microseconds: u64) -> Result<(), EfiStatus> {
+        self.set_timer(
+            EfiTimerDelay::TimerPeriodic,
+            microseconds.saturating_mul(TIMER_TICKS_PER_MICROSECOND),
+        )
+    }
+
+    /// Stop the timer
+    pub fn cancel(&self) -> Result<(), EfiStatus> {
+        self.set_timer(EfiTimerDelay::TimerCancel, 0)
+    }
+
+    /// Stop until the event is signaled
+    pub fn wait(&self) -> Result<(), EfiStatus> {
+        self.b_s.wait_for_events(&[self.event]).map(|_| ())
+    }
+
+    /// Check if the event is signaled without waiting
+    ///
+    /// See [`EfiBootServices::check_event`].
+    pub fn check(&self) -> Result<bool, EfiStatus> {
+        self.b_s.check_event(self.event)
+    }
+
+    pub fn signal(&self) -> Result<(), EfiStatus> {
+        self.b_s.signal_event(self.event)
+    }
+}
+
+impl Drop for Event<'_> {
+    fn drop(&mut self) {
+        let _ = self.b_s.close_event(self.event);
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Image Services of Boot Service
+//!
+
+use super::EfiBootServices;
+
+use super::super::{EfiHandle, EfiStatus};
+
+impl EfiBootServices {
+    /// Load the PE/COFF image in the memory
+    ///
+    /// # Arguments
+    /// * `parent_image_handle` - the handle of the caller
+    /// * `source` - the whole image, it is copied by the firmware
+    ///
+    /// # Result
+    /// If the image is loaded, Ok(the handle of the image), otherwise Err(EfiStatus)
+    pub fn load_image(
+        &self,
+        parent_image_handle: EfiHandle,
+        source: &[u8],
+    ) -> Result<EfiHandle, EfiStatus> {
+        let mut image_handle: EfiHandle = 0;
+        let status = (self.load_image)(
+            false,
+            parent_image_handle,
+            core::ptr::null(),
+            source.as_ptr(),
+            source.len(),
+            &mut image_handle,
+        );
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(image_handle)
+    }
+
+    /// Call the entry point of the loaded image
+    ///
+    /// ExitData of the image is freed.
+    ///
+    /// # Result
+    /// The status which the image returned, Ok(()) if it is EfiSuccess, otherwise Err(EfiStatus)
+    pub fn start_image(&self, image_handle: EfiHandle) -> Result<(), EfiStatus> {
+        let mut exit_data_size = 0;
+        let mut exit_data: *const u16 = core::ptr::null();
+        let status = (self.start_image)(image_handle, &mut exit_data_size, &mut exit_data);
+        if !exit_data.is_null() {
+            let _ = self.free_pool(exit_data as usize);
+        }
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Unload the image which is loaded but not started, or which supports unloading
+    pub fn unload_image(&self, image_handle: EfiHandle) -> Result<(), EfiStatus> {
+        let status = (self.unload_image)(image_handle);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Terminate the image and return `exit_status` to the caller of StartImage()
+    ///
+    /// # Result
+    /// This function returns only when the image cannot be terminated, with the reason
+    pub fn exit(&self, image_handle: EfiHandle, exit_status: EfiStatus) -> EfiStatus {
+        (self.exit)(image_handle, exit_status, 0, core::ptr::null())
+    }
+
+    /// Terminate the boot services
+    ///
+    /// # Arguments
+    /// * `image_handle` - the handle of the caller
+    /// * `map_key` - the key of the latest memory map
+    ///
+    /// # Result
+    /// If the boot services are terminated, Ok(()), otherwise Err(EfiStatus)
+    /// EfiInvalidParameter means that the memory map has been changed.
+    pub fn exit_boot_services(
+        &self,
+        image_handle: EfiHandle,
+        map_key: usize,
+    ) -> Result<(), EfiStatus> {
+        let status = (self.exit_boot_services)(image_handle, map_key);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+}
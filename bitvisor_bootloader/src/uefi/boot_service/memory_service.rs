@@ -14,6 +14,9 @@ use super::EfiBootServices;
 
 use super::super::EfiStatus;
 
+pub const PAGE_SHIFT: usize = 12;
+pub const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug)]
 #[repr(C)]
 pub enum EfiMemoryType {
@@ -113,12 +116,92 @@ impl EfiBootServices {
         &self,
         pages: usize,
         border_address: usize,
+    ) -> Result<usize, EfiStatus> {
+        self.alloc_highest_memory_with_type(pages, border_address, EfiMemoryType::EfiLoaderData)
+    }
+
+    /// Allocate highest memory of `memory_type` under `border_address`
+    ///
+    /// See [`Self::alloc_highest_memory`] for the details.
+    pub fn alloc_highest_memory_with_type(
+        &self,
+        pages: usize,
+        border_address: usize,
+        memory_type: EfiMemoryType,
     ) -> Result<usize, EfiStatus> {
         let mut memory_address = border_address;
         let status = (self.allocate_pages)(
             EfiAllocateType::AllocateMaxAddress,
             //EfiMemoryType::EfiUnusableMemory,
-            EfiMemoryType::EfiLoaderData,
+            memory_type,
+            pages,
+            &mut memory_address as *mut _,
+        );
+
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(memory_address)
+    }
+
+    /// Allocate highest memory aligned to `align` under `border_address`
+    ///
+    /// # Arguments
+    /// * `pages` - the number of needed pages
+    /// * `align` - the alignment of the start address (power of two)
+    /// * `border_address` - the upper border address to restrict to be allocating address
+    /// * `memory_type` - the memory type like EfiLoaderCode
+    ///
+    /// # Result
+    /// If the allocation is succeeded, Ok(start_address), otherwise Err(EfiStatus)
+    pub fn alloc_highest_aligned_memory(
+        &self,
+        pages: usize,
+        align: usize,
+        border_address: usize,
+        memory_type: EfiMemoryType,
+    ) -> Result<usize, EfiStatus> {
+        if align <= PAGE_SIZE {
+            return self.alloc_highest_memory_with_type(pages, border_address, memory_type);
+        }
+        if !align.is_power_of_two() {
+            return Err(EfiStatus::EfiInvalidParameter);
+        }
+        /* Allocate extra pages and give back the unaligned head and tail */
+        let total_pages = pages + (align >> PAGE_SHIFT) - 1;
+        let base_address =
+            self.alloc_highest_memory_with_type(total_pages, border_address, memory_type)?;
+        let aligned_address = (base_address + align - 1) & !(align - 1);
+        let head_pages = (aligned_address - base_address) >> PAGE_SHIFT;
+        let tail_pages = total_pages - head_pages - pages;
+        if head_pages != 0 {
+            self.free_memory(base_address, head_pages)?;
+        }
+        if tail_pages != 0 {
+            self.free_memory(aligned_address + (pages << PAGE_SHIFT), tail_pages)?;
+        }
+        Ok(aligned_address)
+    }
+
+    /// Allocate memory at the demanded address
+    ///
+    /// # Arguments
+    /// * `address` - the page aligned start address
+    /// * `pages` - the number of needed pages
+    /// * `memory_type` - the memory type like EfiLoaderCode
+    ///
+    /// # Result
+    /// If the allocation is succeeded, Ok(start_address), otherwise Err(EfiStatus)
+    pub fn alloc_memory_at(
+        &self,
+        address: usize,
+        pages: usize,
+        memory_type: EfiMemoryType,
+    ) -> Result<usize, EfiStatus> {
+        let mut memory_address = address;
+        let status = (self.allocate_pages)(
+            EfiAllocateType::AllocateAddress,
+            memory_type,
             pages,
             &mut memory_address as *mut _,
         );
@@ -0,0 +1,80 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Miscellaneous Services of Boot Service
+//!
+
+use super::EfiBootServices;
+
+use super::super::{EfiStatus, Guid};
+
+impl EfiBootServices {
+    /// Set the watchdog timer which resets the system after `timeout_seconds`
+    ///
+    /// # Arguments
+    /// * `timeout_seconds` - the time until the reset, 0 disables the timer
+    pub fn set_watchdog_timer(&self, timeout_seconds: usize) -> Result<(), EfiStatus> {
+        let status = (self.set_watchdog_timer)(timeout_seconds, 0, 0, core::ptr::null());
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Get the value which is larger than the value returned last time
+    pub fn get_next_monotonic_count(&self) -> Result<u64, EfiStatus> {
+        let mut count = 0;
+        let status = (self.get_next_monotonic_count)(&mut count);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(count)
+    }
+
+    /// Calculate the 32-bit CRC of `data`
+    pub fn calculate_crc32(&self, data: &[u8]) -> Result<u32, EfiStatus> {
+        let mut crc32 = 0;
+        let status = (self.calculate_crc32)(data.as_ptr(), data.len(), &mut crc32);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(crc32)
+    }
+
+    /// Add, update or remove the entry of the EFI Configuration Table
+    ///
+    /// # Arguments
+    /// * `guid` - the GUID of the entry
+    /// * `table` - the address of the table, null removes the entry
+    pub fn install_configuration_table(
+        &self,
+        guid: &Guid,
+        table: *const usize,
+    ) -> Result<(), EfiStatus> {
+        let status = (self.install_configuration_table)(guid, table);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Copy `source` to `destination` by the firmware, their lengths must be the same
+    pub fn copy_mem(&self, destination: &mut [u8], source: &[u8]) {
+        assert_eq!(destination.len(), source.len());
+        (self.copy_mem)(
+            destination.as_mut_ptr() as usize,
+            source.as_ptr() as usize,
+            source.len(),
+        );
+    }
+
+    /// Fill `buffer` with `value` by the firmware
+    pub fn set_mem(&self, buffer: &mut [u8], value: u8) {
+        (self.set_mem)(buffer.as_mut_ptr() as usize, buffer.len(), value);
+    }
+}
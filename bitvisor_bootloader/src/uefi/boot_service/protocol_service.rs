@@ -0,0 +1,398 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! Protocol Handler Services of Boot Service
+//!
+
+use super::{DevicePathProtocol, EfiBootServices};
+
+use super::super::{EfiEvent, EfiHandle, EfiStatus, Guid};
+
+use core::marker::PhantomData;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub enum EfiInterfaceType {
+    EfiNativeInterface,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub enum EfiLocateSearchType {
+    AllHandles,
+    ByRegisterNotify,
+    ByProtocol,
+}
+
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub struct EfiOpenProtocolInformationEntry {
+    pub agent_handle: EfiHandle,
+    pub controller_handle: EfiHandle,
+    pub attributes: u32,
+    pub open_count: u32,
+}
+
+/// The array allocated from the pool by the boot service
+///
+/// The array must be freed by [`PoolArray::free`].
+pub struct PoolArray<T> {
+    address: usize,
+    length: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T> PoolArray<T> {
+    pub fn as_slice(&self) -> &[T] {
+        if self.length == 0 {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(self.address as *const T, self.length) }
+    }
+
+    /// Free the array
+    pub fn free(self, b_s: &EfiBootServices) -> Result<(), EfiStatus> {
+        if self.address == 0 {
+            return Ok(());
+        }
+        b_s.free_pool(self.address)
+    }
+}
+
+impl EfiBootServices {
+    /// Install the protocol interface on the handle
+    ///
+    /// # Arguments
+    /// * `handle` - the handle to install, 0 makes a new handle
+    /// * `protocol` - the GUID of the protocol
+    /// * `interface` - the address of the interface
+    ///
+    /// # Result
+    /// If the interface is installed, Ok(the handle), otherwise Err(EfiStatus)
+    pub fn install_protocol_interface(
+        &self,
+        handle: EfiHandle,
+        protocol: &Guid,
+        interface: *const usize,
+    ) -> Result<EfiHandle, EfiStatus> {
+        let mut handle = handle;
+        let status = (self.install_protocol_interface)(
+            &mut handle,
+            protocol,
+            EfiInterfaceType::EfiNativeInterface,
+            interface,
+        );
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(handle)
+    }
+
+    /// Replace the protocol interface on the handle
+    pub fn reinstall_protocol_interface(
+        &self,
+        handle: EfiHandle,
+        protocol: &Guid,
+        old_interface: *const usize,
+        new_interface: *const usize,
+    ) -> Result<(), EfiStatus> {
+        let status =
+            (self.reinstall_protocol_interface)(handle, protocol, old_interface, new_interface);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Remove the protocol interface from the handle
+    pub fn uninstall_protocol_interface(
+        &self,
+        handle: EfiHandle,
+        protocol: &Guid,
+        interface: *const usize,
+    ) -> Result<(), EfiStatus> {
+        let status = (self.uninstall_protocol_interface)(handle, protocol, interface);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Get the protocol interface on the handle
+    ///
+    /// # Result
+    /// If the handle supports the protocol, Ok(the address of the interface), otherwise Err(EfiStatus)
+    pub fn handle_protocol(
+        &self,
+        handle: EfiHandle,
+        protocol: &Guid,
+    ) -> Result<*const usize, EfiStatus> {
+        let mut interface: *const usize = core::ptr::null();
+        let status = (self.handle_protocol)(handle, protocol, &mut interface);
+        if status != EfiStatus::EfiSuccess || interface.is_null() {
+            return Err(status);
+        }
+        Ok(interface)
+    }
+
+    /// Signal `event` when the protocol is installed
+    ///
+    /// # Result
+    /// If the notification is registered, Ok(the registration key for locate_handle),
+    /// otherwise Err(EfiStatus)
+    pub fn register_protocol_notify(
+        &self,
+        protocol: &Guid,
+        event: EfiEvent,
+    ) -> Result<*const usize, EfiStatus> {
+        let mut registration: *const usize = core::ptr::null();
+        let status = (self.register_protocol_notify)(protocol, event, &mut registration);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(registration)
+    }
+
+    /// Get the handles which support the protocol into `buffer`
+    ///
+    /// # Arguments
+    /// * `search_type` - AllHandles, ByRegisterNotify or ByProtocol
+    /// * `protocol` - the GUID of the protocol for ByProtocol
+    /// * `search_key` - the registration key for ByRegisterNotify
+    /// * `buffer` - the array to store the handles
+    ///
+    /// # Result
+    /// If one or more handles are stored, Ok(the number of the handles), otherwise Err(EfiStatus)
+    /// EfiBufferTooSmall is returned if `buffer` cannot hold all handles.
+    pub fn locate_handle(
+        &self,
+        search_type: EfiLocateSearchType,
+        protocol: Option<&Guid>,
+        search_key: *const usize,
+        buffer: &mut [EfiHandle],
+    ) -> Result<usize, EfiStatus> {
+        let mut buffer_size = core::mem::size_of_val(buffer);
+        let status = (self.locate_handle)(
+            search_type,
+            protocol.map_or(core::ptr::null(), |p| p as *const Guid),
+            search_key,
+            &mut buffer_size,
+            buffer.as_mut_ptr(),
+        );
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(buffer_size / core::mem::size_of::<EfiHandle>())
+    }
+
+    /// Find the handle of the device which supports the protocol and is the closest to the path
+    ///
+    /// # Result
+    /// If the device is found, Ok((handle, the rest of the path)), otherwise Err(EfiStatus)
+    pub fn locate_device_path(
+        &self,
+        protocol: &Guid,
+        device_path: *const DevicePathProtocol,
+    ) -> Result<(EfiHandle, *const DevicePathProtocol), EfiStatus> {
+        let mut device_path = device_path;
+        let mut device: EfiHandle = 0;
+        let status = (self.locate_device_path)(protocol, &mut device_path, &mut device);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok((device, device_path))
+    }
+
+    /// Connect all drivers to the controller
+    ///
+    /// # Arguments
+    /// * `controller_handle` - the handle of the controller
+    /// * `recursive` - if true, the child controllers are also connected
+    pub fn connect_controller(
+        &self,
+        controller_handle: EfiHandle,
+        recursive: bool,
+    ) -> Result<(), EfiStatus> {
+        let status = (self.connect_controller)(
+            controller_handle,
+            core::ptr::null(),
+            core::ptr::null(),
+            recursive,
+        );
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Disconnect the drivers from the controller
+    ///
+    /// # Arguments
+    /// * `controller_handle` - the handle of the controller
+    /// * `driver_image_handle` - the driver to disconnect, 0 disconnects all drivers
+    /// * `child_handle` - the child to destroy, 0 destroys all children
+    pub fn disconnect_controller(
+        &self,
+        controller_handle: EfiHandle,
+        driver_image_handle: EfiHandle,
+        child_handle: EfiHandle,
+    ) -> Result<(), EfiStatus> {
+        let status =
+            (self.disconnect_controller)(controller_handle, driver_image_handle, child_handle);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Get the address of DisconnectController() to pass it to BitVisor
+    pub fn get_disconnect_controller_address(&self) -> *const usize {
+        self.disconnect_controller as *const usize
+    }
+
+    /// Open the protocol on the handle
+    ///
+    /// # Arguments
+    /// * `handle` - the handle which supports the protocol
+    /// * `protocol` - the GUID of the protocol
+    /// * `agent_handle` - the image handle of the caller
+    /// * `controller_handle` - the controller handle for the drivers, otherwise 0
+    /// * `attributes` - EFI_OPEN_PROTOCOL_*
+    ///
+    /// # Result
+    /// If the protocol is opened, Ok(the address of the interface), otherwise Err(EfiStatus)
+    pub fn open_protocol(
+        &self,
+        handle: EfiHandle,
+        protocol: &Guid,
+        agent_handle: EfiHandle,
+        controller_handle: EfiHandle,
+        attributes: u32,
+    ) -> Result<*const usize, EfiStatus> {
+        let mut interface: *const usize = core::ptr::null();
+        let status = (self.open_protocol)(
+            handle,
+            protocol,
+            &mut interface,
+            agent_handle,
+            controller_handle,
+            attributes,
+        );
+        if status != EfiStatus::EfiSuccess || interface.is_null() {
+            return Err(status);
+        }
+        Ok(interface)
+    }
+
+    /// Close the protocol opened by [`Self::open_protocol`]
+    pub fn close_protocol(
+        &self,
+        handle: EfiHandle,
+        protocol: &Guid,
+        agent_handle: EfiHandle,
+        controller_handle: EfiHandle,
+    ) -> Result<(), EfiStatus> {
+        let status = (self.close_protocol)(handle, protocol, agent_handle, controller_handle);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Get the agents which open the protocol on the handle
+    ///
+    /// # Result
+    /// If the handle supports the protocol, Ok(PoolArray), otherwise Err(EfiStatus)
+    pub fn open_protocol_information(
+        &self,
+        handle: EfiHandle,
+        protocol: &Guid,
+    ) -> Result<PoolArray<EfiOpenProtocolInformationEntry>, EfiStatus> {
+        let mut entry_buffer: *const EfiOpenProtocolInformationEntry = core::ptr::null();
+        let mut entry_count = 0;
+        let status =
+            (self.open_protocol_information)(handle, protocol, &mut entry_buffer, &mut entry_count);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(PoolArray {
+            address: entry_buffer as usize,
+            length: entry_count,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Get the GUIDs of the protocols installed on the handle
+    ///
+    /// # Result
+    /// If the handle is valid, Ok(PoolArray), otherwise Err(EfiStatus)
+    pub fn protocols_per_handle(
+        &self,
+        handle: EfiHandle,
+    ) -> Result<PoolArray<*const Guid>, EfiStatus> {
+        let mut protocol_buffer: *const *const Guid = core::ptr::null();
+        let mut protocol_buffer_count = 0;
+        let status =
+            (self.protocols_per_handle)(handle, &mut protocol_buffer, &mut protocol_buffer_count);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(PoolArray {
+            address: protocol_buffer as usize,
+            length: protocol_buffer_count,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Get the handles which support the protocol
+    ///
+    /// # Arguments
+    /// * `search_type` - AllHandles, ByRegisterNotify or ByProtocol
+    /// * `protocol` - the GUID of the protocol for ByProtocol
+    /// * `search_key` - the registration key for ByRegisterNotify
+    ///
+    /// # Result
+    /// If one or more handles are found, Ok(PoolArray), otherwise Err(EfiStatus)
+    pub fn locate_handle_buffer(
+        &self,
+        search_type: EfiLocateSearchType,
+        protocol: Option<&Guid>,
+        search_key: *const usize,
+    ) -> Result<PoolArray<EfiHandle>, EfiStatus> {
+        let mut buffer: *const EfiHandle = core::ptr::null();
+        let mut no_handles = 0;
+        let status = (self.locate_handle_buffer)(
+            search_type,
+            protocol.map_or(core::ptr::null(), |p| p as *const Guid),
+            search_key,
+            &mut no_handles,
+            &mut buffer,
+        );
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(PoolArray {
+            address: buffer as usize,
+            length: no_handles,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Find the first interface of the protocol
+    ///
+    /// # Result
+    /// If the protocol is found, Ok(the address of the interface), otherwise Err(EfiStatus)
+    pub fn locate_protocol(&self, protocol: &Guid) -> Result<*const usize, EfiStatus> {
+        let mut interface: *const usize = core::ptr::null();
+        let status = (self.locate_protocol)(protocol, core::ptr::null(), &mut interface);
+        if status != EfiStatus::EfiSuccess || interface.is_null() {
+            return Err(status);
+        }
+        Ok(interface)
+    }
+}
@@ -9,11 +9,13 @@
 //! EFI Simple File System Protocol
 //!
 
-use crate::boot_service::{EfiBootServices, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL};
+use crate::boot_service::{
+    EfiBootServices, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL, PAGE_SHIFT, PAGE_SIZE,
+};
 use crate::println;
 use crate::uefi::loaded_image::{EfiLoadedImageProtocol, EFI_LOADED_IMAGE_PROTOCOL_GUID};
 use crate::uefi::{EfiHandle, EfiStatus, EfiTime, Guid};
-use core::mem::MaybeUninit;
+use core::ops::Deref;
 
 const EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID: Guid = Guid {
     d1: 0x0964e5b22,
@@ -41,7 +43,6 @@ const EFI_FILE_HIDDEN: u64 = 0x0000000000000002;
 const EFI_FILE_SYSTEM: u64 = 0x0000000000000004;
 #[allow(dead_code)]
 const EFI_FILE_RESERVED: u64 = 0x0000000000000008;
-#[allow(dead_code)]
 const EFI_FILE_DIRECTORY: u64 = 0x0000000000000010;
 #[allow(dead_code)]
 const EFI_FILE_ARCHIVE: u64 = 0x0000000000000020;
@@ -73,8 +74,124 @@ pub struct EfiFileInfo {
     pub last_access_time: EfiTime,
     pub modification_time: EfiTime,
     pub attribute: u64,
-    // TODO: fix this part
-    pub file_name: [u16; 32],
+    /// The null-terminated file name follows this header, use [`FileInfo::file_name`]
+    file_name: [u16; 0],
+}
+
+/// EfiFileInfo with the file name stored in a pool allocation
+///
+/// The allocation is freed on drop.
+pub struct FileInfo<'a> {
+    b_s: &'a EfiBootServices,
+    buffer: usize,
+    buffer_size: usize,
+}
+
+impl<'a> FileInfo<'a> {
+    /// Get the file name in UTF-16 without the null terminator
+    pub fn file_name(&self) -> &[u16] {
+        let name_offset = core::mem::offset_of!(EfiFileInfo, file_name);
+        let max_length = self.buffer_size.saturating_sub(name_offset) / 2;
+        let name = unsafe {
+            core::slice::from_raw_parts((self.buffer + name_offset) as *const u16, max_length)
+        };
+        let length = name.iter().position(|&c| c == 0).unwrap_or(max_length);
+        &name[..length]
+    }
+
+    pub fn is_directory(&self) -> bool {
+        (self.attribute & EFI_FILE_DIRECTORY) != 0
+    }
+}
+
+impl<'a> Deref for FileInfo<'a> {
+    type Target = EfiFileInfo;
+
+    fn deref(&self) -> &EfiFileInfo {
+        unsafe { &*(self.buffer as *const EfiFileInfo) }
+    }
+}
+
+/// Iterator over the entries of a directory
+///
+/// Each entry is read as EFI_FILE_INFO by [`EfiFileProtocol::read`] on the directory handle.
+/// "." and ".." are also returned as the firmware reports them.
+pub struct DirectoryIterator<'a> {
+    directory: &'a EfiFileProtocol,
+    b_s: &'a EfiBootServices,
+    is_finished: bool,
+}
+
+impl<'a> Iterator for DirectoryIterator<'a> {
+    type Item = Result<FileInfo<'a>, EfiStatus>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+        match self.directory.read_directory_entry(self.b_s) {
+            Ok(Some(info)) => Some(Ok(info)),
+            Ok(None) => {
+                self.is_finished = true;
+                None
+            }
+            Err(e) => {
+                self.is_finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'a> Drop for FileInfo<'a> {
+    fn drop(&mut self) {
+        let _ = self.b_s.free_pool(self.buffer);
+    }
+}
+
+/// Pages holding the contents of a file
+pub struct FileBuffer {
+    address: usize,
+    pages: usize,
+    size: usize,
+}
+
+impl FileBuffer {
+    /// Allocate pages to store `size` bytes
+    ///
+    /// # Arguments
+    /// * `b_s` - EfiBootService
+    /// * `size` - the size of the buffer in bytes
+    ///
+    /// # Result
+    /// If the allocation is succeeded, Ok(FileBuffer), otherwise Err(EfiStatus)
+    pub fn allocate(b_s: &EfiBootServices, size: usize) -> Result<Self, EfiStatus> {
+        let pages = ((size + PAGE_SIZE - 1) >> PAGE_SHIFT).max(1);
+        let address = b_s.alloc_highest_memory(pages, usize::MAX)?;
+        Ok(Self {
+            address,
+            pages,
+            size,
+        })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.address as *const u8, self.size) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.address as *mut u8, self.size) }
+    }
+
+    /// Free the pages
+    pub fn free(&self, b_s: &EfiBootServices) {
+        if let Err(e) = b_s.free_memory(self.address, self.pages) {
+            println!(
+                "Failed to free the file buffer at {:#X}: {:?}",
+                self.address, e
+            );
+        }
+    }
 }
 
 #[repr(C)]
@@ -133,33 +250,22 @@ impl EfiFileProtocol {
         b_s: &EfiBootServices,
     ) -> Result<&'static EfiFileProtocol, EfiStatus> {
         let mut root_dir_protocol: *const EfiFileProtocol = core::ptr::null();
-        let mut loaded_image_protocol: *const EfiLoadedImageProtocol = core::ptr::null();
-        let mut simple_file_protocol: *const EfiSimpleFileProtocol = core::ptr::null();
 
-        let status = (b_s.open_protocol)(
+        let loaded_image_protocol = b_s.open_protocol(
             image_handle,
             &EFI_LOADED_IMAGE_PROTOCOL_GUID,
-            &mut loaded_image_protocol as *mut _ as usize as *mut *const usize,
             image_handle,
             0,
             EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
-        );
-
-        if status != EfiStatus::EfiSuccess || loaded_image_protocol.is_null() {
-            return Err(status);
-        };
-        let status = (b_s.open_protocol)(
+        )? as *const EfiLoadedImageProtocol;
+        let simple_file_protocol = b_s.open_protocol(
             unsafe { (*loaded_image_protocol).device_handle },
             &EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID,
-            &mut simple_file_protocol as *mut _ as usize as *mut *const usize,
             image_handle,
             0,
             EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
-        );
+        )? as *const EfiSimpleFileProtocol;
 
-        if status != EfiStatus::EfiSuccess || simple_file_protocol.is_null() {
-            return Err(status);
-        };
         let status = unsafe {
             ((*simple_file_protocol).open_volume)(
                 simple_file_protocol,
@@ -212,19 +318,50 @@ impl EfiFileProtocol {
         Ok(unsafe { &*file_handle })
     }
 
-    pub fn get_file_info(&self) -> Result<EfiFileInfo, EfiStatus> {
-        let mut result = MaybeUninit::<EfiFileInfo>::uninit();
-        let mut read_size = core::mem::size_of::<EfiFileInfo>();
+    /// Get EfiFileInfo of the file
+    ///
+    /// The needed size is queried first, so the file name is not truncated.
+    ///
+    /// # Arguments
+    /// * `b_s` - EfiBootService to allocate the buffer
+    ///
+    /// # Result
+    /// If the information is retrieved, Ok(FileInfo), otherwise Err(EfiStatus)
+    pub fn get_file_info<'a>(&self, b_s: &'a EfiBootServices) -> Result<FileInfo<'a>, EfiStatus> {
+        let mut buffer_size = 0;
+        let status = (self.get_info)(
+            self,
+            &EFI_FILE_INFO_GUID,
+            &mut buffer_size,
+            core::ptr::null_mut(),
+        );
+        if status != EfiStatus::EfiBufferTooSmall {
+            return Err(if status == EfiStatus::EfiSuccess {
+                EfiStatus::EfiDeviceError
+            } else {
+                status
+            });
+        }
+        if buffer_size < core::mem::size_of::<EfiFileInfo>() {
+            buffer_size = core::mem::size_of::<EfiFileInfo>();
+        }
+
+        let buffer = b_s.alloc_pool(buffer_size)?;
         let status = (self.get_info)(
             self,
             &EFI_FILE_INFO_GUID,
-            &mut read_size,
-            result.as_mut_ptr() as *mut _,
+            &mut buffer_size,
+            buffer as *mut u8,
         );
         if status != EfiStatus::EfiSuccess {
+            let _ = b_s.free_pool(buffer);
             return Err(status);
         }
-        Ok(unsafe { result.assume_init() })
+        Ok(FileInfo {
+            b_s,
+            buffer,
+            buffer_size,
+        })
     }
 
     pub fn read(&self, buffer: *mut usize, buffer_size: usize) -> Result<usize, EfiStatus> {
@@ -236,6 +373,92 @@ impl EfiFileProtocol {
         Ok(read_size)
     }
 
+    /// List the entries of the directory from the beginning
+    ///
+    /// # Arguments
+    /// * `b_s` - EfiBootService to allocate each entry
+    ///
+    /// # Result
+    /// If this is a directory and it is rewound, Ok(DirectoryIterator), otherwise Err(EfiStatus)
+    pub fn read_dir<'a>(
+        &'a self,
+        b_s: &'a EfiBootServices,
+    ) -> Result<DirectoryIterator<'a>, EfiStatus> {
+        if !self.get_file_info(b_s)?.is_directory() {
+            return Err(EfiStatus::EfiInvalidParameter);
+        }
+        self.seek(0)?;
+        Ok(DirectoryIterator {
+            directory: self,
+            b_s,
+            is_finished: false,
+        })
+    }
+
+    /// Read the next EFI_FILE_INFO from the directory
+    ///
+    /// # Result
+    /// Ok(Some(FileInfo)) for an entry, Ok(None) at the end of the directory, otherwise Err(EfiStatus)
+    fn read_directory_entry<'a>(
+        &self,
+        b_s: &'a EfiBootServices,
+    ) -> Result<Option<FileInfo<'a>>, EfiStatus> {
+        let mut buffer_size = 0;
+        let status = (self.read)(self, &mut buffer_size, core::ptr::null_mut());
+        if status == EfiStatus::EfiSuccess && buffer_size == 0 {
+            return Ok(None);
+        } else if status != EfiStatus::EfiBufferTooSmall {
+            return Err(status);
+        }
+
+        let buffer = b_s.alloc_pool(buffer_size)?;
+        let status = (self.read)(self, &mut buffer_size, buffer as *mut usize);
+        if status != EfiStatus::EfiSuccess || buffer_size < core::mem::size_of::<EfiFileInfo>() {
+            let _ = b_s.free_pool(buffer);
+            return Err(if status == EfiStatus::EfiSuccess {
+                EfiStatus::EfiVolumeCorrupted
+            } else {
+                status
+            });
+        }
+        Ok(Some(FileInfo {
+            b_s,
+            buffer,
+            buffer_size,
+        }))
+    }
+
+    /// Read the whole file into newly allocated pages
+    ///
+    /// The pages are allocated by `file_size` and the file is read until the end.
+    ///
+    /// # Arguments
+    /// * `b_s` - EfiBootService to allocate the pages
+    ///
+    /// # Result
+    /// If the file is read, Ok(FileBuffer), otherwise Err(EfiStatus).
+    /// The size of FileBuffer may be smaller than `file_size` if the file ends earlier.
+    pub fn read_to_end(&self, b_s: &EfiBootServices) -> Result<FileBuffer, EfiStatus> {
+        let file_size = self.get_file_info(b_s)?.file_size;
+        let mut file_buffer = FileBuffer::allocate(b_s, file_size)?;
+        let mut read_size = 0;
+        while read_size < file_size {
+            match self.read(
+                (file_buffer.address + read_size) as *mut usize,
+                file_size - read_size,
+            ) {
+                Ok(0) => break,
+                Ok(s) => read_size += s,
+                Err(e) => {
+                    file_buffer.free(b_s);
+                    return Err(e);
+                }
+            }
+        }
+        file_buffer.size = read_size;
+        Ok(file_buffer)
+    }
+
     pub fn write(&self, buffer: *mut u8, buffer_size: usize) -> Result<usize, EfiStatus> {
         let mut write_size = buffer_size;
         let status = (self.write)(self, &mut write_size as *mut _, buffer);
@@ -245,6 +468,31 @@ impl EfiFileProtocol {
         Ok(write_size)
     }
 
+    /// Write the whole data
+    ///
+    /// # Result
+    /// If all bytes are written, Ok(()), otherwise Err(EfiStatus)
+    pub fn write_all(&self, data: &[u8]) -> Result<(), EfiStatus> {
+        let mut written = 0;
+        while written < data.len() {
+            let rest = &data[written..];
+            match self.write(rest.as_ptr() as *mut u8, rest.len())? {
+                0 => return Err(EfiStatus::EfiDeviceError),
+                size => written += size,
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the cached data to the device
+    pub fn flush(&self) -> Result<(), EfiStatus> {
+        let status = (self.flush)(self);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
     pub fn seek(&self, position: usize) -> Result<(), EfiStatus> {
         let status = (self.set_position)(self, position as u64);
         if status != EfiStatus::EfiSuccess {
@@ -253,6 +501,15 @@ impl EfiFileProtocol {
         Ok(())
     }
 
+    /// Delete the file, the handle is closed even if it fails
+    pub fn delete(&'static self) -> Result<(), EfiStatus> {
+        let status = (self.delete)(self);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
     pub fn close_file(&'static self) -> Result<(), EfiStatus> {
         let s = ((*self).close)(self);
         if s == EfiStatus::EfiSuccess {
@@ -0,0 +1,95 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! EFI Graphics Output Protocol
+//!
+
+use super::boot_service::EfiBootServices;
+use super::{EfiStatus, Guid};
+
+pub const EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID: Guid = Guid {
+    d1: 0x9042A9DE,
+    d2: 0x23DC,
+    d3: 0x4A38,
+    d4: [0x96, 0xFB, 0x7A, 0xDE, 0xD0, 0x80, 0x51, 0x6A],
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum EfiGraphicsPixelFormat {
+    PixelRedGreenBlueReserved8BitPerColor,
+    PixelBlueGreenRedReserved8BitPerColor,
+    PixelBitMask,
+    PixelBltOnly,
+    PixelFormatMax,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct EfiPixelBitmask {
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub reserved_mask: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct EfiGraphicsOutputModeInformation {
+    pub version: u32,
+    pub horizontal_resolution: u32,
+    pub vertical_resolution: u32,
+    pub pixel_format: EfiGraphicsPixelFormat,
+    pub pixel_information: EfiPixelBitmask,
+    pub pixels_per_scan_line: u32,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EfiGraphicsOutputProtocolMode {
+    pub max_mode: u32,
+    pub mode: u32,
+    pub info: *const EfiGraphicsOutputModeInformation,
+    pub size_of_info: usize,
+    pub frame_buffer_base: usize,
+    pub frame_buffer_size: usize,
+}
+
+#[repr(C)]
+pub struct EfiGraphicsOutputProtocol {
+    query_mode: extern "efiapi" fn(
+        this: *const Self,
+        mode_number: u32,
+        size_of_info: *mut usize,
+        info: *mut *const EfiGraphicsOutputModeInformation,
+    ) -> EfiStatus,
+    set_mode: extern "efiapi" fn(this: *const Self, mode_number: u32) -> EfiStatus,
+    blt: usize,
+    mode: *const EfiGraphicsOutputProtocolMode,
+}
+
+impl EfiGraphicsOutputProtocol {
+    /// Locate the protocol of the first display
+    ///
+    /// # Result
+    /// If the firmware has the protocol, Ok(protocol), otherwise Err(EfiStatus)
+    pub fn locate(b_s: &EfiBootServices) -> Result<&'static Self, EfiStatus> {
+        let interface = b_s.locate_protocol(&EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID)?;
+        Ok(unsafe { &*(interface as *const Self) })
+    }
+
+    /// Get the current mode and the framebuffer
+    pub fn get_mode(&self) -> &EfiGraphicsOutputProtocolMode {
+        unsafe { &*self.mode }
+    }
+
+    /// Get the resolution and the pixel format of the current mode
+    pub fn get_mode_info(&self) -> &EfiGraphicsOutputModeInformation {
+        unsafe { &*self.get_mode().info }
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! EFI Simple Text Input Protocol
+//!
+
+use super::{EfiEvent, EfiStatus};
+
+pub const SCAN_NULL: u16 = 0x00;
+pub const SCAN_UP: u16 = 0x01;
+pub const SCAN_DOWN: u16 = 0x02;
+pub const SCAN_RIGHT: u16 = 0x03;
+pub const SCAN_LEFT: u16 = 0x04;
+pub const SCAN_HOME: u16 = 0x05;
+pub const SCAN_END: u16 = 0x06;
+pub const SCAN_INSERT: u16 = 0x07;
+pub const SCAN_DELETE: u16 = 0x08;
+pub const SCAN_PAGE_UP: u16 = 0x09;
+pub const SCAN_PAGE_DOWN: u16 = 0x0A;
+pub const SCAN_F1: u16 = 0x0B;
+pub const SCAN_F2: u16 = 0x0C;
+pub const SCAN_F3: u16 = 0x0D;
+pub const SCAN_F4: u16 = 0x0E;
+pub const SCAN_F5: u16 = 0x0F;
+pub const SCAN_F6: u16 = 0x10;
+pub const SCAN_F7: u16 = 0x11;
+pub const SCAN_F8: u16 = 0x12;
+pub const SCAN_F9: u16 = 0x13;
+pub const SCAN_F10: u16 = 0x14;
+pub const SCAN_ESC: u16 = 0x17;
+
+pub const CHAR_NULL: u16 = 0x00;
+pub const CHAR_BACKSPACE: u16 = 0x08;
+pub const CHAR_TAB: u16 = 0x09;
+pub const CHAR_LINEFEED: u16 = 0x0A;
+pub const CHAR_CARRIAGE_RETURN: u16 = 0x0D;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct EfiInputKey {
+    pub scan_code: u16,
+    pub unicode_char: u16,
+}
+
+impl EfiInputKey {
+    /// Get the character of the key
+    ///
+    /// # Result
+    /// If the key is a printable character, Some(char), otherwise None
+    pub fn get_char(&self) -> Option<char> {
+        if self.unicode_char == CHAR_NULL {
+            return None;
+        }
+        char::from_u32(self.unicode_char as u32).filter(|c| !c.is_control())
+    }
+}
+
+#[repr(C)]
+pub struct EfiInputProtocol {
+    reset: extern "efiapi" fn(*const EfiInputProtocol, bool) -> EfiStatus,
+    read_key_stroke: extern "efiapi" fn(*const EfiInputProtocol, *mut EfiInputKey) -> EfiStatus,
+    wait_for_key: EfiEvent,
+}
+
+impl EfiInputProtocol {
+    /// Reset the input device and discard the pending keys
+    ///
+    /// # Arguments
+    /// * `extended_verification` - should execute extended verification(this will be passed to UEFI)
+    pub fn reset(&self, extended_verification: bool) -> EfiStatus {
+        (self.reset)(self as *const _, extended_verification)
+    }
+
+    /// Read the next key from the input buffer
+    ///
+    /// # Result
+    /// If a key is pressed, Ok(EfiInputKey), if not, Err(EfiStatus::EfiNotReady)
+    pub fn read_key_stroke(&self) -> Result<EfiInputKey, EfiStatus> {
+        let mut key = EfiInputKey::default();
+        let status = (self.read_key_stroke)(self as *const _, &mut key);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(key)
+    }
+
+    /// Get the event signaled when a key is available
+    pub fn wait_for_key(&self) -> EfiEvent {
+        self.wait_for_key
+    }
+}
@@ -9,7 +9,8 @@
 //! EFI Loaded Image Protocol
 //!
 
-use super::{boot_service::EfiMemoryType, EfiHandle, EfiStatus, EfiSystemTable, Guid};
+use super::boot_service::{EfiBootServices, EfiMemoryType, EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL};
+use super::{EfiHandle, EfiStatus, EfiSystemTable, Guid};
 
 pub const EFI_LOADED_IMAGE_PROTOCOL_GUID: Guid = Guid {
     d1: 0x5B1B31A1,
@@ -33,3 +34,42 @@ pub struct EfiLoadedImageProtocol {
     pub image_data_type: EfiMemoryType,
     pub unload: extern "efiapi" fn(image_handle: EfiHandle) -> EfiStatus,
 }
+
+impl EfiLoadedImageProtocol {
+    /// Get the EfiLoadedImageProtocol of the image
+    ///
+    /// # Arguments
+    /// * `image_handle` - the handle of the image
+    /// * `b_s` - EfiBootService
+    ///
+    /// # Result
+    /// If the protocol is opened, Ok(&EfiLoadedImageProtocol), otherwise Err(EfiStatus)
+    pub fn open(
+        image_handle: EfiHandle,
+        b_s: &EfiBootServices,
+    ) -> Result<&'static Self, EfiStatus> {
+        let interface = b_s.open_protocol(
+            image_handle,
+            &EFI_LOADED_IMAGE_PROTOCOL_GUID,
+            image_handle,
+            0,
+            EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL,
+        )?;
+        Ok(unsafe { &*(interface as *const Self) })
+    }
+
+    /// Get LoadOptions as bytes
+    ///
+    /// The buffer may be neither aligned nor a multiple of the size of UTF-16 characters.
+    pub fn get_load_options(&self) -> &[u8] {
+        if self.load_options == 0 {
+            return &[];
+        }
+        unsafe {
+            core::slice::from_raw_parts(
+                self.load_options as *const u8,
+                self.load_option_size as usize,
+            )
+        }
+    }
+}
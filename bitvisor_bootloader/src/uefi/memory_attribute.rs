@@ -0,0 +1,96 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! EFI Memory Attribute Protocol
+//!
+//! This protocol is defined in UEFI 2.10
+//!
+
+use super::boot_service::EfiBootServices;
+use super::{EfiStatus, Guid};
+
+pub const EFI_MEMORY_ATTRIBUTE_PROTOCOL_GUID: Guid = Guid {
+    d1: 0xf4560cf6,
+    d2: 0x40ec,
+    d3: 0x4b4a,
+    d4: [0xa1, 0x92, 0xbf, 0x1d, 0x57, 0xd0, 0xb1, 0x89],
+};
+
+#[repr(C)]
+pub struct EfiMemoryAttributeProtocol {
+    get_memory_attributes: extern "efiapi" fn(
+        this: *const Self,
+        base_address: u64,
+        length: u64,
+        attributes: *mut u64,
+    ) -> EfiStatus,
+    set_memory_attributes: extern "efiapi" fn(
+        this: *const Self,
+        base_address: u64,
+        length: u64,
+        attributes: u64,
+    ) -> EfiStatus,
+    clear_memory_attributes: extern "efiapi" fn(
+        this: *const Self,
+        base_address: u64,
+        length: u64,
+        attributes: u64,
+    ) -> EfiStatus,
+}
+
+impl EfiMemoryAttributeProtocol {
+    /// Locate the protocol
+    ///
+    /// # Result
+    /// If the firmware has the protocol, Ok(protocol), otherwise Err(EfiStatus)
+    pub fn locate(b_s: &EfiBootServices) -> Result<&'static Self, EfiStatus> {
+        let interface = b_s.locate_protocol(&EFI_MEMORY_ATTRIBUTE_PROTOCOL_GUID)?;
+        Ok(unsafe { &*(interface as *const Self) })
+    }
+
+    /// Get the attributes (EFI_MEMORY_RP, EFI_MEMORY_XP and EFI_MEMORY_RO) of the range
+    pub fn get_attributes(&self, base_address: usize, length: usize) -> Result<u64, EfiStatus> {
+        let mut attributes = 0u64;
+        let status =
+            (self.get_memory_attributes)(self, base_address as u64, length as u64, &mut attributes);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(attributes)
+    }
+
+    /// Set `attributes` to the page aligned range
+    pub fn set_attributes(
+        &self,
+        base_address: usize,
+        length: usize,
+        attributes: u64,
+    ) -> Result<(), EfiStatus> {
+        let status =
+            (self.set_memory_attributes)(self, base_address as u64, length as u64, attributes);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Clear `attributes` from the page aligned range
+    pub fn clear_attributes(
+        &self,
+        base_address: usize,
+        length: usize,
+        attributes: u64,
+    ) -> Result<(), EfiStatus> {
+        let status =
+            (self.clear_memory_attributes)(self, base_address as u64, length as u64, attributes);
+        if status != EfiStatus::EfiSuccess {
+            return Err(status);
+        }
+        Ok(())
+    }
+}
@@ -11,18 +11,56 @@
 
 use super::EfiStatus;
 
+pub const EFI_BLACK: usize = 0x00;
+pub const EFI_BLUE: usize = 0x01;
+pub const EFI_GREEN: usize = 0x02;
+pub const EFI_CYAN: usize = 0x03;
+pub const EFI_RED: usize = 0x04;
+pub const EFI_MAGENTA: usize = 0x05;
+pub const EFI_BROWN: usize = 0x06;
+pub const EFI_LIGHTGRAY: usize = 0x07;
+pub const EFI_DARKGRAY: usize = 0x08;
+pub const EFI_LIGHTBLUE: usize = 0x09;
+pub const EFI_LIGHTGREEN: usize = 0x0A;
+pub const EFI_LIGHTCYAN: usize = 0x0B;
+pub const EFI_LIGHTRED: usize = 0x0C;
+pub const EFI_LIGHTMAGENTA: usize = 0x0D;
+pub const EFI_YELLOW: usize = 0x0E;
+pub const EFI_WHITE: usize = 0x0F;
+
+/// Make the attribute for set_attribute
+///
+/// # Arguments
+/// * `foreground` - EFI_BLACK ~ EFI_WHITE
+/// * `background` - EFI_BLACK ~ EFI_LIGHTGRAY
+pub const fn efi_text_attr(foreground: usize, background: usize) -> usize {
+    (foreground & 0x0F) | ((background & 0x07) << 4)
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EfiSimpleTextOutputMode {
+    pub max_mode: i32,
+    pub mode: i32,
+    pub attribute: i32,
+    pub cursor_column: i32,
+    pub cursor_row: i32,
+    pub cursor_visible: bool,
+}
+
 #[repr(C)]
 pub struct EfiOutputProtocol {
     reset: extern "efiapi" fn(*const EfiOutputProtocol, bool) -> EfiStatus,
     output_string: extern "efiapi" fn(*const EfiOutputProtocol, *const u16) -> EfiStatus,
-    test_string: usize,
-    query_mode: usize,
-    set_mode: usize,
-    set_attribute: usize,
-    clear_screen: usize,
-    set_cursor_position: usize,
-    enable_cursor: usize,
-    mode: usize,
+    test_string: extern "efiapi" fn(*const EfiOutputProtocol, *const u16) -> EfiStatus,
+    query_mode:
+        extern "efiapi" fn(*const EfiOutputProtocol, usize, *mut usize, *mut usize) -> EfiStatus,
+    set_mode: extern "efiapi" fn(*const EfiOutputProtocol, usize) -> EfiStatus,
+    set_attribute: extern "efiapi" fn(*const EfiOutputProtocol, usize) -> EfiStatus,
+    clear_screen: extern "efiapi" fn(*const EfiOutputProtocol) -> EfiStatus,
+    set_cursor_position: extern "efiapi" fn(*const EfiOutputProtocol, usize, usize) -> EfiStatus,
+    enable_cursor: extern "efiapi" fn(*const EfiOutputProtocol, bool) -> EfiStatus,
+    mode: *const EfiSimpleTextOutputMode,
 }
 
 impl EfiOutputProtocol {
@@ -71,4 +109,65 @@ impl EfiOutputProtocol {
         buf[pointer] = 0;
         (self.output_string)(self as *const _, buf.as_ptr())
     }
+
+    /// Get the number of the columns and the rows of the text mode
+    ///
+    /// # Arguments
+    /// * `mode_number` - the mode number from 0 to max_mode - 1
+    ///
+    /// # Result
+    /// If the mode is supported, Ok((columns, rows)), otherwise Err(EfiStatus)
+    pub fn query_mode(&self, mode_number: usize) -> Result<(usize, usize), EfiStatus> {
+        let mut columns = 0;
+        let mut rows = 0;
+        let status = (self.query_mode)(self as *const _, mode_number, &mut columns, &mut rows);
+        if status == EfiStatus::EfiSuccess {
+            Ok((columns, rows))
+        } else {
+            Err(status)
+        }
+    }
+
+    /// Change the text mode, the screen is cleared
+    ///
+    /// # Arguments
+    /// * `mode_number` - the mode number from 0 to max_mode - 1
+    pub fn set_mode(&self, mode_number: usize) -> EfiStatus {
+        (self.set_mode)(self as *const _, mode_number)
+    }
+
+    /// Set the colors of the following output
+    ///
+    /// # Arguments
+    /// * `attribute` - the foreground and background colors made by [`efi_text_attr`]
+    pub fn set_attribute(&self, attribute: usize) -> EfiStatus {
+        (self.set_attribute)(self as *const _, attribute)
+    }
+
+    /// Clear the screen with the background color and move the cursor to (0, 0)
+    pub fn clear_screen(&self) -> EfiStatus {
+        (self.clear_screen)(self as *const _)
+    }
+
+    /// Move the cursor
+    ///
+    /// # Arguments
+    /// * `column` - the column starting from 0
+    /// * `row` - the row starting from 0
+    pub fn set_cursor_position(&self, column: usize, row: usize) -> EfiStatus {
+        (self.set_cursor_position)(self as *const _, column, row)
+    }
+
+    /// Show or hide the cursor
+    ///
+    /// # Arguments
+    /// * `visible` - true to show the cursor
+    pub fn enable_cursor(&self, visible: bool) -> EfiStatus {
+        (self.enable_cursor)(self as *const _, visible)
+    }
+
+    /// Get the current mode, attribute and cursor position
+    pub fn get_mode(&self) -> &EfiSimpleTextOutputMode {
+        unsafe { &*self.mode }
+    }
 }
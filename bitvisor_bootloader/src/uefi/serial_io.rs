@@ -0,0 +1,116 @@
+// Copyright (c) 2022 RIKEN
+// Copyright (c) 2022 National Institute of Advanced Industrial Science and Technology (AIST)
+// All rights reserved.
+//
+// This software is released under the MIT License.
+// http://opensource.org/licenses/mit-license.php
+
+//!
+//! EFI Serial I/O Protocol
+//!
+
+use super::boot_service::EfiBootServices;
+use super::{EfiStatus, Guid};
+
+pub const EFI_SERIAL_IO_PROTOCOL_GUID: Guid = Guid {
+    d1: 0xBB25CF6F,
+    d2: 0xF1D4,
+    d3: 0x11D2,
+    d4: [0x9A, 0x0C, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0xFD],
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum EfiParityType {
+    DefaultParity,
+    NoParity,
+    EvenParity,
+    OddParity,
+    MarkParity,
+    SpaceParity,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum EfiStopBitsType {
+    DefaultStopBits,
+    OneStopBit,
+    OneFiveStopBits,
+    TwoStopBits,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct EfiSerialIoMode {
+    pub control_mask: u32,
+    pub timeout: u32,
+    pub baud_rate: u64,
+    pub receive_fifo_depth: u32,
+    pub data_bits: u32,
+    pub parity: u32,
+    pub stop_bits: u32,
+}
+
+#[repr(C)]
+pub struct EfiSerialIoProtocol {
+    pub revision: u32,
+    reset: extern "efiapi" fn(this: *const Self) -> EfiStatus,
+    set_attributes: extern "efiapi" fn(
+        this: *const Self,
+        baud_rate: u64,
+        receive_fifo_depth: u32,
+        timeout: u32,
+        parity: EfiParityType,
+        data_bits: u8,
+        stop_bits: EfiStopBitsType,
+    ) -> EfiStatus,
+    set_control: extern "efiapi" fn(this: *const Self, control: u32) -> EfiStatus,
+    get_control: extern "efiapi" fn(this: *const Self, control: *mut u32) -> EfiStatus,
+    write: extern "efiapi" fn(
+        this: *const Self,
+        buffer_size: *mut usize,
+        buffer: *const u8,
+    ) -> EfiStatus,
+    read: extern "efiapi" fn(
+        this: *const Self,
+        buffer_size: *mut usize,
+        buffer: *mut u8,
+    ) -> EfiStatus,
+    mode: *const EfiSerialIoMode,
+}
+
+impl EfiSerialIoProtocol {
+    /// Locate the first serial device
+    ///
+    /// # Result
+    /// If the firmware has the protocol, Ok(protocol), otherwise Err(EfiStatus)
+    pub fn locate(b_s: &EfiBootServices) -> Result<&'static Self, EfiStatus> {
+        let interface = b_s.locate_protocol(&EFI_SERIAL_IO_PROTOCOL_GUID)?;
+        Ok(unsafe { &*(interface as *const Self) })
+    }
+
+    /// Write the whole buffer to the device
+    ///
+    /// # Result
+    /// If all bytes are written, Ok(()), otherwise Err(EfiStatus)
+    pub fn write(&self, buffer: &[u8]) -> Result<(), EfiStatus> {
+        let mut written = 0;
+        while written < buffer.len() {
+            let mut buffer_size = buffer.len() - written;
+            let status = (self.write)(self, &mut buffer_size, buffer[written..].as_ptr());
+            if status != EfiStatus::EfiSuccess {
+                return Err(status);
+            }
+            if buffer_size == 0 {
+                return Err(EfiStatus::EfiDeviceError);
+            }
+            written += buffer_size;
+        }
+        Ok(())
+    }
+
+    /// Get the current baud rate and the line settings
+    pub fn get_mode(&self) -> &EfiSerialIoMode {
+        unsafe { &*self.mode }
+    }
+}